@@ -1,24 +1,19 @@
-//! This example demonstrates the use of `send_async()` (incubating) to make a request asynchronously using the unstable
-//! futures API.
+//! This example demonstrates the use of `send_async()` (incubating) to make a request asynchronously using
+//! `async`/`await`.
 use chttp::Client;
 use chttp::http::Request;
 use futures::executor;
-use futures::prelude::*;
 
 fn main() -> Result<(), chttp::Error> {
     env_logger::init();
     let client = Client::new()?;
 
-    let future = client
-        .send_async(Request::get("http://example.org").body(())?)
-        .map(|response| {
-            println!("Status: {}", response.status());
-            println!("Headers:\n{:?}", response.headers());
+    executor::block_on(async {
+        let response = client.send_async(Request::get("http://example.org").body(())?).await?;
 
-            response
-        });
+        println!("Status: {}", response.status());
+        println!("Headers:\n{:?}", response.headers());
 
-    executor::block_on(future)?;
-
-    Ok(())
+        Ok::<(), chttp::Error>(())
+    })
 }
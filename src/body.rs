@@ -3,30 +3,235 @@
 use bytes::Bytes;
 use crate::error::Error;
 use crate::internal;
+use futures::executor;
+use futures::io::AsyncRead;
+use http::header::HeaderValue;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::pin::Pin;
 use std::str;
+use futures::task::{Context, Poll};
+
+#[cfg(feature = "async-api")]
+use futures::stream::{Stream, TryStreamExt};
+
+pub mod multipart;
+
+/// Upper limit on how much memory to allocate up front based on a body's reported length, in case the length reported
+/// by a server is bogus.
+const MAX_PREALLOCATE_SIZE: u64 = 16 * 1024 * 1024;
 
 /// Contains the body of an HTTP request or response.
 ///
 /// This type is used to encapsulate the underlying stream or region of memory where the contents of the body is stored.
 /// A `Body` can be created from many types of sources using the [`Into`](std::convert::Into) trait.
-pub struct Body(Inner);
+///
+/// With the `async-api` feature enabled, `Body` also implements
+/// [`AsyncRead`](https://docs.rs/futures-preview/0.3.0-alpha.19/futures/io/trait.AsyncRead.html), which allows response
+/// bodies to be read inside of an async context without blocking the executor.
+pub struct Body {
+    inner: Inner,
+    /// A media type describing the contents of the body, if known.
+    content_type: Option<HeaderValue>,
+}
 
 enum Inner {
     /// An empty body.
     Empty,
     /// A body stored in memory.
     Bytes(Cursor<Bytes>),
-    /// A body read from a stream.
-    Streaming(Box<Read + Send>),
+    /// A body read from a stream, with an optional known length.
+    Streaming(Box<Read + Send>, Option<u64>),
+    /// A body read from an asynchronous stream, with an optional known length.
+    AsyncStreaming(Box<dyn AsyncRead + Send + Unpin>, Option<u64>),
 }
 
 impl Body {
+    fn from_inner(inner: Inner) -> Self {
+        Self {
+            inner,
+            content_type: None,
+        }
+    }
+
+    /// Create a new empty body.
+    ///
+    /// An empty body has a known length of zero.
+    pub fn empty() -> Body {
+        Body::from_inner(Inner::Empty)
+    }
+
     /// Create a body from a reader.
+    ///
+    /// The length of the body is unknown, so the body will be sent using chunked transfer encoding when used as a
+    /// request body.
     pub fn from_reader(reader: impl Read + Send + 'static) -> Body {
-        Body(Inner::Streaming(Box::new(reader)))
+        Body::from_inner(Inner::Streaming(Box::new(reader), None))
+    }
+
+    /// Create a body from a reader with a known length.
+    ///
+    /// The length given will be used to set the `Content-Length` header when used as a request body, so it must match
+    /// the number of bytes the reader will produce.
+    pub fn from_reader_sized(reader: impl Read + Send + 'static, len: u64) -> Body {
+        Body::from_inner(Inner::Streaming(Box::new(reader), Some(len)))
+    }
+
+    /// Create a body that streams the contents of the file at the given path.
+    ///
+    /// The length of the body is taken from the file's metadata, so that the `Content-Length` header is set correctly
+    /// when the body is used as a request body. A content type is guessed from the file extension for common file
+    /// types, which can be replaced using [`with_content_type`](#method.with_content_type).
+    ///
+    /// ```rust
+    /// use chttp::Body;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let response = chttp::put("https://example.org/upload/photo.png", Body::from_file("photo.png")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Body> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let body = Body::from_reader_sized(file, len);
+
+        Ok(match guess_content_type(path) {
+            Some(content_type) => body.with_content_type(HeaderValue::from_static(content_type)),
+            None => body,
+        })
+    }
+
+    /// Create a body that streams chunks of data from an asynchronous stream.
+    ///
+    /// When used as a request body, chunks are sent as they become available. The upload is paused whenever the stream
+    /// has no data ready and resumed once it does, so a slow producer holds back the transfer instead of blocking the
+    /// client's event loop, and data is only pulled from the stream as fast as it can be sent. An error returned by the
+    /// stream aborts the request.
+    ///
+    /// Since the length of the stream is not known up front, the body is sent using chunked transfer encoding.
+    ///
+    /// ```rust
+    /// use chttp::Body;
+    /// use futures::stream;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let chunks = stream::iter(vec![Ok::<_, std::io::Error>("hello ".into()), Ok("world".into())]);
+    /// let response = chttp::post("https://httpbin.org/post", Body::from_stream(chunks))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async-api")]
+    pub fn from_stream<S, E>(stream: S) -> Body
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let reader = Box::pin(stream)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            .into_async_read();
+
+        Body::from_async_reader(reader)
+    }
+
+    /// Create a body from an asynchronous reader.
+    pub(crate) fn from_async_reader(reader: impl AsyncRead + Send + Unpin + 'static) -> Body {
+        Body::from_inner(Inner::AsyncStreaming(Box::new(reader), None))
+    }
+
+    /// Create a body from an asynchronous reader with a known length.
+    pub(crate) fn from_async_reader_sized(reader: impl AsyncRead + Send + Unpin + 'static, len: u64) -> Body {
+        Body::from_inner(Inner::AsyncStreaming(Box::new(reader), Some(len)))
+    }
+
+    /// Attempt to read from the body without blocking if it comes from an asynchronous stream, registering the given
+    /// context to be woken once more data is available. Other bodies are read synchronously.
+    pub(crate) fn poll_read_nonblocking(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if let Inner::AsyncStreaming(reader, _) = &mut self.inner {
+            return Pin::new(reader).poll_read(cx, buf);
+        }
+
+        // Other bodies are either stored in memory, or come from a synchronous reader that can only be read by blocking.
+        Poll::Ready(self.read(buf))
+    }
+
+    /// Create a body containing form data in the `application/x-www-form-urlencoded` format.
+    ///
+    /// Each name and value is percent-encoded as UTF-8, and pairs are kept in the given order, so the same name may
+    /// appear more than once. The `Content-Type` header of a request using this body is set automatically.
+    ///
+    /// ```rust
+    /// use chttp::Body;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let response = chttp::post("https://example.org/search", Body::form(&[("q", "hello world")]))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn form(pairs: &[(&str, &str)]) -> Body {
+        let mut encoded = String::new();
+
+        for (name, value) in pairs {
+            if !encoded.is_empty() {
+                encoded.push('&');
+            }
+            form_urlencode(name, &mut encoded);
+            encoded.push('=');
+            form_urlencode(value, &mut encoded);
+        }
+
+        Body::from(encoded).with_content_type(HeaderValue::from_static("application/x-www-form-urlencoded"))
+    }
+
+    /// Get the media type of the body contents, if known.
+    ///
+    /// If a request does not have a `Content-Type` header, this value will be used instead.
+    pub(crate) fn content_type(&self) -> Option<&HeaderValue> {
+        self.content_type.as_ref()
+    }
+
+    /// Set the media type of the body contents.
+    ///
+    /// If a request using this body does not have a `Content-Type` header, this value is sent as the header instead.
+    pub fn with_content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Guess the media type of the body contents by looking at its first few bytes.
+    ///
+    /// Only bodies stored in memory can be inspected; `None` is returned for streaming bodies, as well as when the
+    /// contents do not match any known format. The following formats are recognized:
+    ///
+    /// - Images: PNG, JPEG, GIF and WebP.
+    /// - Documents: PDF, HTML, XML and JSON. JSON is detected by a leading `{` or `[`, so it is only a guess.
+    /// - Archives: ZIP and gzip, as well as WebAssembly modules.
+    ///
+    /// ```rust
+    /// use chttp::Body;
+    ///
+    /// let body = Body::from(&b"\x89PNG\r\n\x1a\n"[..]);
+    /// assert_eq!(body.sniff_content_type(), Some("image/png"));
+    /// ```
+    pub fn sniff_content_type(&self) -> Option<&'static str> {
+        match &self.inner {
+            Inner::Bytes(bytes) => sniff_content_type(bytes.get_ref()),
+            _ => None,
+        }
+    }
+
+    /// Set the media type of the body contents to the one guessed by
+    /// [`sniff_content_type`](#method.sniff_content_type), unless a media type is already set or none can be guessed.
+    pub fn with_sniffed_content_type(self) -> Self {
+        match self.sniff_content_type() {
+            Some(content_type) if self.content_type.is_none() => {
+                self.with_content_type(HeaderValue::from_static(content_type))
+            },
+            _ => self,
+        }
     }
 
     /// Report if this body is empty.
@@ -35,18 +240,39 @@ impl Body {
     }
 
     /// Get the size of the body, if known.
-    pub fn len(&self) -> Option<usize> {
-        match &self.0 {
+    pub fn len(&self) -> Option<u64> {
+        match &self.inner {
             Inner::Empty => Some(0),
-            Inner::Bytes(bytes) => Some(bytes.get_ref().len()),
-            Inner::Streaming(_) => None,
+            Inner::Bytes(bytes) => Some(bytes.get_ref().len() as u64),
+            Inner::Streaming(_, len) => *len,
+            Inner::AsyncStreaming(_, len) => *len,
         }
     }
 
+    /// Create a copy of this body, if the body is stored in memory.
+    ///
+    /// The copy always starts reading from the beginning of the content. Returns `None` for streaming bodies, which
+    /// cannot be read more than once. This is useful for deciding whether a request can be sent again, such as when
+    /// retrying a failed request.
+    ///
+    /// `Body` does not implement `Clone`, since not every body can be cloned.
+    pub fn try_clone(&self) -> Option<Body> {
+        let inner = match &self.inner {
+            Inner::Empty => Inner::Empty,
+            Inner::Bytes(bytes) => Inner::Bytes(Cursor::new(bytes.get_ref().clone())),
+            Inner::Streaming(_, _) | Inner::AsyncStreaming(_, _) => return None,
+        };
+
+        Some(Body {
+            inner,
+            content_type: self.content_type.clone(),
+        })
+    }
+
     /// If this body is repeatable, reset the body stream back to the start of
     /// the content. Returns `false` if the body cannot be reset.
     pub fn reset(&mut self) -> bool {
-        match &mut self.0 {
+        match &mut self.inner {
             Inner::Empty => true,
             Inner::Bytes(bytes) => {
                 bytes.set_position(0);
@@ -56,46 +282,183 @@ impl Body {
         }
     }
 
+    /// Read the entire body into a byte vector.
+    ///
+    /// If the size of the body is known, then the capacity of the vector is allocated up front. If the underlying
+    /// stream returns an error partway through, the error is returned and any bytes read so far are discarded.
+    pub fn bytes(&mut self) -> Result<Vec<u8>, io::Error> {
+        let capacity = self.len().unwrap_or(0).min(MAX_PREALLOCATE_SIZE) as usize;
+        let mut bytes = Vec::with_capacity(capacity);
+        self.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
     /// Get the response body as a string.
     ///
     /// If the body comes from a stream, the steam bytes will be consumed and this method will return an empty string
     /// next call. If this body supports seeking, you can seek to the beginning of the body if you need to call this
     /// method again later.
     pub fn text(&mut self) -> Result<String, Error> {
-        match &mut self.0 {
+        match &mut self.inner {
             Inner::Empty => Ok(String::new()),
             Inner::Bytes(bytes) => str::from_utf8(bytes.get_ref())
                 .map(Into::into)
                 .map_err(Into::into),
-            Inner::Streaming(reader) => {
+            Inner::Streaming(reader, _) => {
                 let mut string = String::new();
                 reader.read_to_string(&mut string)?;
                 Ok(string)
             },
+            Inner::AsyncStreaming(reader, _) => {
+                let mut string = String::new();
+                executor::block_on(futures::io::AsyncReadExt::read_to_string(reader, &mut string))?;
+                Ok(string)
+            },
         }
     }
 
-    /// Attempt to parse the response as JSON.
+    /// Read the entire body into a byte vector without blocking the current thread.
+    ///
+    /// This is the asynchronous equivalent of [`bytes`](#method.bytes), for use with response bodies inside of an
+    /// async context.
+    #[cfg(feature = "async-api")]
+    pub async fn bytes_async(&mut self) -> Result<Vec<u8>, io::Error> {
+        let capacity = self.len().unwrap_or(0).min(MAX_PREALLOCATE_SIZE) as usize;
+        let mut bytes = Vec::with_capacity(capacity);
+        futures::io::AsyncReadExt::read_to_end(self, &mut bytes).await?;
+        Ok(bytes)
+    }
+
+    /// Get the response body as a string without blocking the current thread.
+    ///
+    /// This is the asynchronous equivalent of [`text`](#method.text), for use with response bodies inside of an async
+    /// context.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    ///
+    /// # async fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let mut response = client.send_async(chttp::http::Request::get("https://example.org").body(())?).await?;
+    /// println!("{}", response.body_mut().text_async().await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async-api")]
+    pub async fn text_async(&mut self) -> Result<String, Error> {
+        let bytes = self.bytes_async().await?;
+        String::from_utf8(bytes).map_err(|e| e.utf8_error().into())
+    }
+
+    /// Deserialize the body as JSON into a value of a given type.
+    ///
+    /// The JSON is parsed directly from the body stream as it is read, without buffering the entire body in memory
+    /// first. If the body is not valid JSON or does not match the expected type, `Error::InvalidJson` is returned,
+    /// while errors reading the stream itself are returned as I/O errors.
     #[cfg(feature = "json")]
-    pub fn json(&mut self) -> Result<json::JsonValue, Error> {
-        let text = self.text()?;
-        Ok(json::parse(&text)?)
+    pub fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, Error> {
+        // serde_json reads one byte at a time, which is slow without a buffer.
+        Ok(serde_json::from_reader(io::BufReader::new(self))?)
+    }
+}
+
+/// Guess the media type of a file from its extension.
+fn guess_content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    Some(match extension.as_str() {
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "gif" => "image/gif",
+        "gz" => "application/gzip",
+        "htm" | "html" => "text/html",
+        "jpeg" | "jpg" => "image/jpeg",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "tar" => "application/x-tar",
+        "txt" => "text/plain",
+        "wasm" => "application/wasm",
+        "webp" => "image/webp",
+        "xml" => "application/xml",
+        "zip" => "application/zip",
+        _ => return None,
+    })
+}
+
+/// Guess the media type of some data from the magic bytes at its start.
+fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\0asm", "application/wasm"),
+    ];
+
+    if let Some((_, content_type)) = SIGNATURES.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return Some(*content_type);
+    }
+
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    // Text formats may start with whitespace.
+    let start = bytes.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let text = &bytes[start..bytes.len().min(start + 16)];
+    let starts_with = |prefix: &[u8]| text.len() >= prefix.len() && text[..prefix.len()].eq_ignore_ascii_case(prefix);
+
+    if starts_with(b"<!doctype html") || starts_with(b"<html") {
+        Some("text/html")
+    } else if starts_with(b"<?xml") {
+        Some("application/xml")
+    } else if starts_with(b"{") || starts_with(b"[") {
+        Some("application/json")
+    } else {
+        None
+    }
+}
+
+/// Percent-encode a string according to the `application/x-www-form-urlencoded` serializer.
+fn form_urlencode(value: &str, dest: &mut String) {
+    for byte in value.bytes() {
+        match byte {
+            b'*' | b'-' | b'.' | b'_' | b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' => dest.push(byte as char),
+            b' ' => dest.push('+'),
+            _ => dest.push_str(&format!("%{:02X}", byte)),
+        }
     }
 }
 
 impl Read for Body {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match &mut self.0 {
+        match &mut self.inner {
             Inner::Empty => Ok(0),
             Inner::Bytes(bytes) => bytes.read(buf),
-            Inner::Streaming(reader) => reader.read(buf),
+            Inner::Streaming(reader, _) => reader.read(buf),
+            Inner::AsyncStreaming(reader, _) => executor::block_on(futures::io::AsyncReadExt::read(reader, buf)),
         }
     }
 }
 
+#[cfg(feature = "async-api")]
+impl AsyncRead for Body {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_read_nonblocking(cx, buf)
+    }
+}
+
 impl Default for Body {
     fn default() -> Self {
-        Body(Inner::Empty)
+        Body::empty()
     }
 }
 
@@ -119,7 +482,7 @@ impl<'a> From<&'a [u8]> for Body {
 
 impl From<Bytes> for Body {
     fn from(body: Bytes) -> Self {
-        Body(Inner::Bytes(Cursor::new(body)))
+        Body::from_inner(Inner::Bytes(Cursor::new(body)))
     }
 }
 
@@ -137,7 +500,10 @@ impl<'a> From<&'a str> for Body {
 
 impl From<File> for Body {
     fn from(body: File) -> Self {
-        Self::from_reader(body)
+        match body.metadata() {
+            Ok(metadata) => Self::from_reader_sized(body, metadata.len()),
+            Err(_) => Self::from_reader(body),
+        }
     }
 }
 
@@ -152,10 +518,44 @@ impl<T: Into<Body>> From<Option<T>> for Body {
 
 impl fmt::Debug for Body {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.0 {
+        match &self.inner {
             Inner::Empty => write!(f, "Empty"),
             Inner::Bytes(bytes) => write!(f, "Memory({})", internal::format_byte_string(bytes.get_ref())),
-            Inner::Streaming(_) => write!(f, "Streaming"),
+            Inner::Streaming(_, _) | Inner::AsyncStreaming(_, _) => write!(f, "Streaming"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_body_is_urlencoded() {
+        let mut body = Body::form(&[("q", "hello world"), ("q", "a&b=c"), ("name", "J\u{fc}rgen")]);
+
+        assert_eq!(body.content_type().unwrap(), "application/x-www-form-urlencoded");
+        assert_eq!(body.text().unwrap(), "q=hello+world&q=a%26b%3Dc&name=J%C3%BCrgen");
+    }
+
+    #[test]
+    fn content_type_is_guessed_from_extension() {
+        assert_eq!(guess_content_type(Path::new("photo.PNG")), Some("image/png"));
+        assert_eq!(guess_content_type(Path::new("dir/data.json")), Some("application/json"));
+        assert_eq!(guess_content_type(Path::new("README")), None);
+        assert_eq!(guess_content_type(Path::new("archive.unknown")), None);
+    }
+
+    #[test]
+    fn content_type_is_sniffed_from_contents() {
+        assert_eq!(Body::from(&b"\xff\xd8\xff\xe0"[..]).sniff_content_type(), Some("image/jpeg"));
+        assert_eq!(Body::from(&b"RIFF\0\0\0\0WEBPVP8 "[..]).sniff_content_type(), Some("image/webp"));
+        assert_eq!(Body::from("  <!DOCTYPE html><html>").sniff_content_type(), Some("text/html"));
+        assert_eq!(Body::from("\n{\"a\": 1}").sniff_content_type(), Some("application/json"));
+        assert_eq!(Body::from("hello").sniff_content_type(), None);
+        assert_eq!(Body::from_reader(&b"%PDF-1.4"[..]).sniff_content_type(), None);
+
+        let body = Body::from("<?xml version=\"1.0\"?>").with_sniffed_content_type();
+        assert_eq!(body.content_type().unwrap(), "application/xml");
+    }
+}
@@ -0,0 +1,265 @@
+//! Support for building `multipart/form-data` request bodies.
+//!
+//! Multipart forms are typically used for uploading files alongside regular form fields. A [`Form`](struct.Form.html)
+//! can be converted into a [`Body`](../struct.Body.html), which will set the correct `Content-Type` header for the
+//! request automatically.
+//!
+//! ```rust
+//! use chttp::body::multipart::Form;
+//!
+//! # fn run() -> Result<(), chttp::Error> {
+//! let form = Form::new()
+//!     .text("name", "Ferris")
+//!     .file("avatar", "ferris.png");
+//!
+//! let response = chttp::post("https://example.org", form)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::body::Body;
+use crate::internal;
+use http::header::HeaderValue;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::PathBuf;
+
+/// A `multipart/form-data` form builder.
+///
+/// Text fields are stored in memory, while file fields are opened and streamed lazily only when the body is sent.
+#[derive(Debug)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug)]
+struct Part {
+    name: String,
+    source: Source,
+}
+
+#[derive(Debug)]
+enum Source {
+    Text(String),
+    File(PathBuf),
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Form {
+    /// Create a new, empty form with a randomly generated boundary.
+    pub fn new() -> Self {
+        Self {
+            boundary: format!("------------------------{:016x}{:016x}", internal::random_u64(), internal::random_u64()),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Get the boundary string used to separate parts of this form.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Add a text field to the form.
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parts.push(Part {
+            name: name.into(),
+            source: Source::Text(value.into()),
+        });
+        self
+    }
+
+    /// Add a file field to the form.
+    ///
+    /// The file is not opened until the form is actually being sent. If the file cannot be opened at that time, the
+    /// request will fail with a request body error.
+    pub fn file(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.parts.push(Part {
+            name: name.into(),
+            source: Source::File(path.into()),
+        });
+        self
+    }
+}
+
+impl From<Form> for Body {
+    fn from(form: Form) -> Body {
+        let content_type = HeaderValue::from_str(&format!("multipart/form-data; boundary={}", form.boundary)).unwrap();
+        let mut reader = ChainReader::default();
+        let mut len = Some(0);
+
+        for part in form.parts {
+            let mut header = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", form.boundary, escape(&part.name));
+
+            match part.source {
+                Source::Text(text) => {
+                    header.push_str("\r\n\r\n");
+                    reader.push(header.into_bytes(), &mut len);
+                    reader.push(text.into_bytes(), &mut len);
+                },
+                Source::File(path) => {
+                    if let Some(filename) = path.file_name() {
+                        header.push_str(&format!("; filename=\"{}\"", escape(&filename.to_string_lossy())));
+                    }
+                    header.push_str("\r\nContent-Type: application/octet-stream\r\n\r\n");
+                    reader.push(header.into_bytes(), &mut len);
+
+                    // The file size is needed up front in order to know the total length of the body.
+                    let file_len = fs::metadata(&path).ok().map(|metadata| metadata.len());
+                    len = match (len, file_len) {
+                        (Some(len), Some(file_len)) => Some(len + file_len),
+                        _ => None,
+                    };
+                    reader.readers.push_back(Box::new(LazyFile {
+                        path,
+                        expected_len: file_len,
+                        file: None,
+                    }));
+                },
+            }
+
+            reader.push(b"\r\n".to_vec(), &mut len);
+        }
+
+        reader.push(format!("--{}--\r\n", form.boundary).into_bytes(), &mut len);
+
+        let body = match len {
+            Some(len) => Body::from_reader_sized(reader, len),
+            None => Body::from_reader(reader),
+        };
+
+        body.with_content_type(content_type)
+    }
+}
+
+/// Escape a field name or file name for use inside a quoted header parameter.
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Reads from a sequence of readers, one after another.
+#[derive(Default)]
+struct ChainReader {
+    readers: VecDeque<Box<Read + Send>>,
+}
+
+impl ChainReader {
+    /// Append a chunk of bytes to the end of the chain, adding its size to the given length.
+    fn push(&mut self, bytes: Vec<u8>, len: &mut Option<u64>) {
+        if let Some(len) = len.as_mut() {
+            *len += bytes.len() as u64;
+        }
+        self.readers.push_back(Box::new(Cursor::new(bytes)));
+    }
+}
+
+impl Read for ChainReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(reader) = self.readers.front_mut() {
+            match reader.read(buf)? {
+                0 if !buf.is_empty() => {
+                    self.readers.pop_front();
+                },
+                amount => return Ok(amount),
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// A file that is not opened until it is first read from.
+struct LazyFile {
+    path: PathBuf,
+    /// The size of the file when the body was created, which is part of the length of the body.
+    expected_len: Option<u64>,
+    file: Option<File>,
+}
+
+impl Read for LazyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.file.is_none() {
+            let file = File::open(&self.path)?;
+
+            // Sending a different amount of data than announced would corrupt the request.
+            if let Some(expected_len) = self.expected_len {
+                let len = file.metadata()?.len();
+
+                if len != expected_len {
+                    return Err(io::Error::new(io::ErrorKind::Other, format!(
+                        "file {} changed size from {} to {} bytes before it was sent",
+                        self.path.display(),
+                        expected_len,
+                        len,
+                    )));
+                }
+            }
+
+            self.file = Some(file);
+        }
+
+        self.file.as_mut().unwrap().read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_fields_are_encoded() {
+        let form = Form::new()
+            .text("foo", "bar")
+            .text("baz", "hello world");
+        let boundary = form.boundary().to_owned();
+
+        let mut body = Body::from(form);
+        let len = body.len();
+        let text = body.text().unwrap();
+
+        assert_eq!(text, format!(
+            "--{0}\r\nContent-Disposition: form-data; name=\"foo\"\r\n\r\nbar\r\n\
+             --{0}\r\nContent-Disposition: form-data; name=\"baz\"\r\n\r\nhello world\r\n\
+             --{0}--\r\n",
+            boundary,
+        ));
+        assert_eq!(len, Some(text.len() as u64));
+    }
+
+    #[test]
+    fn file_changing_size_fails_read() {
+        let path = std::env::temp_dir().join(format!("chttp-multipart-{}", std::process::id()));
+        fs::write(&path, "hello").unwrap();
+
+        let mut body = Body::from(Form::new().file("file", &path));
+        fs::write(&path, "hello world").unwrap();
+
+        let result = body.text();
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn boundaries_are_unique() {
+        assert_ne!(Form::new().boundary(), Form::new().boundary());
+    }
+
+    #[test]
+    fn content_type_includes_boundary() {
+        let form = Form::new();
+        let expected = format!("multipart/form-data; boundary={}", form.boundary());
+        let body = Body::from(form);
+
+        assert_eq!(body.content_type().unwrap(), expected.as_str());
+    }
+}
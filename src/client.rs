@@ -2,29 +2,68 @@
 
 use crate::body::Body;
 use crate::error::Error;
+use crate::internal;
 use crate::internal::agent;
 use crate::internal::request;
-use crate::middleware::Middleware;
+use crate::metrics::ClientMetrics;
+use crate::middleware::{Middleware, Next, Transport};
 use crate::options::*;
+use crate::resolver::{self, Resolver};
 use futures::executor;
+use futures::future::BoxFuture;
 use futures::prelude::*;
+use futures::task::{Context, Poll};
 use http::{Request, Response};
 use lazy_static::lazy_static;
-use std::sync::Arc;
+use log::warn;
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 lazy_static! {
     static ref USER_AGENT: String = format!("curl/{} chttp/{}", curl::Version::get().version(), env!("CARGO_PKG_VERSION"));
 }
 
+lazy_static! {
+    /// Default options for the global client, or `None` once the global client has been created.
+    static ref GLOBAL_OPTIONS: Mutex<Option<Options>> = Mutex::new(Some(Options::default()));
+}
+
 /// Get a reference to a global client instance.
 pub(crate) fn global() -> &'static Client {
     lazy_static! {
-        static ref CLIENT: Client = Client::new().unwrap();
+        static ref CLIENT: Client = {
+            // Holding the lock while building makes sure that the options cannot be changed after they are taken.
+            let mut options = GLOBAL_OPTIONS.lock().unwrap();
+
+            Client::builder()
+                .options(options.take().unwrap_or_default())
+                .build()
+                .unwrap()
+        };
     }
 
     &CLIENT
 }
 
+/// Set the default options of the global client, if it has not been created yet.
+pub(crate) fn configure_global(options: Options) -> Result<(), Error> {
+    match GLOBAL_OPTIONS.lock().unwrap().as_mut() {
+        Some(global_options) => {
+            *global_options = options;
+            Ok(())
+        },
+        None => Err(Error::AlreadyInitialized),
+    }
+}
+
 /// An HTTP client builder, capable of creating custom [`Client`](struct.Client.html) instances with customized
 /// behavior.
 ///
@@ -49,8 +88,15 @@ pub(crate) fn global() -> &'static Client {
 /// # }
 /// ```
 pub struct ClientBuilder {
+    agent_config: agent::Config,
+    default_headers: http::HeaderMap,
     default_options: Options,
+    user_agent: Option<String>,
     middleware: Vec<Box<dyn Middleware>>,
+    resolver: Option<Arc<dyn Resolver>>,
+    event_loop: Option<EventLoop>,
+    #[cfg(feature = "mock")]
+    mock: Option<crate::mock::Mock>,
 }
 
 impl Default for ClientBuilder {
@@ -63,11 +109,88 @@ impl ClientBuilder {
     /// Create a new builder for building a custom client.
     pub fn new() -> Self {
         Self {
+            agent_config: agent::Config::default(),
+            default_headers: http::HeaderMap::new(),
             default_options: Options::default(),
+            user_agent: None,
             middleware: Vec::new(),
+            resolver: None,
+            event_loop: None,
+            #[cfg(feature = "mock")]
+            mock: None,
         }
     }
 
+    /// Set the maximum number of connections the client may keep open at once.
+    ///
+    /// Requests that would exceed this limit are queued until an existing connection becomes available. Since each
+    /// client has its own connection pool, this limit applies per client.
+    ///
+    /// The default is unlimited.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.agent_config.max_connections = Some(max);
+        self
+    }
+
+    /// Set the maximum number of connections the client may keep open to a single host at once.
+    ///
+    /// Requests that would exceed this limit are queued until an existing connection to the host becomes available.
+    ///
+    /// The default is unlimited.
+    pub fn max_connections_per_host(mut self, max: usize) -> Self {
+        self.agent_config.max_connections_per_host = Some(max);
+        self
+    }
+
+    /// Run the client on the given event loop instead of starting a new one, sharing it with other clients.
+    ///
+    /// By default, each client runs its own event loop on a dedicated thread with its own connection pool, which
+    /// isolates clients from each other. Clients sharing an event loop use a single thread and connection pool
+    /// instead, which saves resources when creating many clients, but a busy client can then slow down the others, and
+    /// usage statistics and [`shutdown`](struct.Client.html#method.shutdown) apply to all of them together. The
+    /// connection limits of an event loop are those of the client it was created with, so
+    /// [`max_connections`](#method.max_connections) and
+    /// [`max_connections_per_host`](#method.max_connections_per_host) have no effect when this is set.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    /// use chttp::client::EventLoop;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let event_loop = EventLoop::new()?;
+    /// let first = Client::builder().event_loop(event_loop.clone()).build()?;
+    /// let second = Client::builder().event_loop(event_loop).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn event_loop(mut self, event_loop: EventLoop) -> Self {
+        self.event_loop = Some(event_loop);
+        self
+    }
+
+    /// Set headers to include in every request sent by the client.
+    ///
+    /// Each default header is only added to a request if the request does not already include a header with the same
+    /// name, so headers set on individual requests always take precedence. Setting a default `User-Agent` header
+    /// replaces the user agent the client sends by default.
+    pub fn default_headers(mut self, headers: http::HeaderMap) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Set the `User-Agent` header to send with every request that does not set its own.
+    ///
+    /// This replaces the user agent the client sends by default, and takes precedence over a `User-Agent` header
+    /// given in [`default_headers`](#method.default_headers).
+    ///
+    /// If the user agent contains characters that are not allowed in a header value, such as newlines, then
+    /// [`build`](#method.build) fails with
+    /// [`Error::InvalidHttpFormat`](../error/enum.Error.html#variant.InvalidHttpFormat).
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
     /// Set the default connection options to use for each request.
     ///
     /// If a request has custom options, then they will override any options specified here.
@@ -76,18 +199,59 @@ impl ClientBuilder {
         self
     }
 
+    /// Set a custom resolver to use for looking up the addresses of hosts instead of DNS.
+    ///
+    /// The resolver is asked for the addresses of the host of each request, and the results are passed to curl along
+    /// with the request. Hosts that requests are redirected to are not passed to the resolver, and are looked up
+    /// using DNS instead. See [`Resolver`](../resolver/trait.Resolver.html) for details.
+    pub fn resolver(mut self, resolver: impl Resolver) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Answer all requests using the given mock instead of sending them over the network.
+    ///
+    /// Requests still pass through any middleware, so that their behavior is included in tests, but request options
+    /// have no effect. No event loop is started for the client. See the [`mock`](../mock/index.html) module for
+    /// details.
+    #[cfg(feature = "mock")]
+    pub fn mock(mut self, mock: crate::mock::Mock) -> Self {
+        self.mock = Some(mock);
+        self
+    }
+
     /// Enable persistent cookie handling using a cookie jar.
     #[cfg(feature = "cookies")]
     pub fn with_cookies(self) -> Self {
         self.with_middleware_impl(crate::cookies::CookieJar::default())
     }
 
+    /// Enable persistent cookie handling using the given cookie jar.
+    ///
+    /// Since cookie jars are shared handles, this can be used to keep access to the client's cookies, such as for
+    /// saving them to a file later.
+    #[cfg(feature = "cookies")]
+    pub fn cookie_jar(self, jar: crate::cookies::CookieJar) -> Self {
+        self.with_middleware_impl(jar)
+    }
+
     /// Add a middleware layer to the client.
     #[cfg(feature = "middleware-api")]
     pub fn with_middleware(self, middleware: impl Middleware) -> Self {
         self.with_middleware_impl(middleware)
     }
 
+    fn default_headers_with_user_agent(&self) -> Result<http::HeaderMap, Error> {
+        let mut headers = self.default_headers.clone();
+
+        if let Some(user_agent) = self.user_agent.as_ref() {
+            let user_agent = http::header::HeaderValue::from_str(user_agent).map_err(Into::<http::Error>::into)?;
+            headers.insert(http::header::USER_AGENT, user_agent);
+        }
+
+        Ok(headers)
+    }
+
     #[allow(unused)]
     fn with_middleware_impl(mut self, middleware: impl Middleware) -> Self {
         self.middleware.push(Box::new(middleware));
@@ -98,24 +262,119 @@ impl ClientBuilder {
     ///
     /// If the client fails to initialize, an error will be returned.
     pub fn build(&mut self) -> Result<Client, Error> {
-        let agent = agent::create()?;
+        let default_headers = self.default_headers_with_user_agent()?;
+
+        // A mock answers requests in place of an agent, so there is no need to start one.
+        #[cfg(feature = "mock")]
+        {
+            if let Some(mock) = self.mock.as_ref() {
+                let transport = mock.transport();
+                return Ok(self.build_with(default_headers, None, transport));
+            }
+        }
+
+        let agent = match self.event_loop.as_ref() {
+            Some(event_loop) => event_loop.agent.clone(),
+            None => agent::create(self.agent_config.clone())?,
+        };
+        let transport = create_transport(agent.clone(), self.default_options.clone(), self.resolver.clone());
 
-        Ok(Client {
+        Ok(self.build_with(default_headers, Some(agent), transport))
+    }
+
+    fn build_with(
+        &mut self,
+        default_headers: http::HeaderMap,
+        agent: Option<agent::Handle>,
+        transport: Transport,
+    ) -> Client {
+        Client {
             agent: agent,
+            agent_config: self.agent_config.clone(),
+            default_headers: default_headers,
             default_options: self.default_options.clone(),
             middleware: Arc::new(self.middleware.drain(..).collect()),
+            resolver: self.resolver.clone(),
+            transport: transport,
+            #[cfg(feature = "mock")]
+            mock: self.mock.clone(),
+        }
+    }
+}
+
+/// An event loop that sends requests and receives responses for one or more clients.
+///
+/// Each event loop runs on its own thread and has its own connection pool. Every client runs on an event loop, which is
+/// either started along with it or given to its builder using
+/// [`ClientBuilder::event_loop`](struct.ClientBuilder.html#method.event_loop) to share it with other clients. The event
+/// loop keeps running until it is shut down or all clients using it and all handles to it have been dropped.
+#[derive(Clone, Debug)]
+pub struct EventLoop {
+    agent: agent::Handle,
+}
+
+impl EventLoop {
+    /// Start a new event loop without any connection limits.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            agent: agent::create(agent::Config::default())?,
         })
     }
 }
 
+/// Create the function that sends requests using the given agent once they have passed through all middleware.
+fn create_transport(agent: agent::Handle, default_options: Options, resolver: Option<Arc<dyn Resolver>>) -> Transport {
+    // Whether a warning about disabled certificate verification has been logged yet.
+    let warned_ssl_verify = AtomicBool::new(false);
+
+    Arc::new(move |mut request: crate::Request| {
+        // Extract the request options, or use the default options.
+        let options = request.extensions_mut().remove::<Options>();
+        let mut options = match options {
+            Some(options) => Cow::Owned(options),
+            None => Cow::Borrowed(&default_options),
+        };
+
+        if let Some(resolver) = resolver.as_ref() {
+            resolver::apply(&**resolver, request.uri(), options.to_mut());
+        }
+
+        if !options.ssl_verify && !warned_ssl_verify.swap(true, Ordering::Relaxed) {
+            warn!("SSL/TLS certificate verification is disabled, connections are vulnerable to man-in-the-middle attacks");
+        }
+
+        let result = request::create(request, &options)
+            .and_then(|(request, future)| {
+                agent.begin_execute(request).map(|_| future)
+            });
+
+        match result {
+            Ok(future) => future.boxed(),
+            Err(e) => future::ready(Err(e)).boxed(),
+        }
+    })
+}
+
 /// An HTTP client for making requests.
 ///
 /// The client maintains a connection pool internally and is expensive to create, so we recommend re-using your clients
 /// instead of discarding and recreating them.
+///
+/// Cloning a client is cheap. Clones share the same connection pool and event loop as the original client, so they are
+/// not isolated from each other, and the event loop keeps running until the last clone is dropped. Use
+/// [`with_options`](#method.with_options) to get a clone with different default options.
+#[derive(Clone)]
 pub struct Client {
-    agent: agent::Handle,
+    /// The agent sending requests, or `None` if requests are answered by a mock instead.
+    agent: Option<agent::Handle>,
+    agent_config: agent::Config,
+    default_headers: http::HeaderMap,
     default_options: Options,
     middleware: Arc<Vec<Box<dyn Middleware>>>,
+    resolver: Option<Arc<dyn Resolver>>,
+    transport: Transport,
+    #[cfg(feature = "mock")]
+    mock: Option<crate::mock::Mock>,
 }
 
 impl Client {
@@ -131,6 +390,116 @@ impl Client {
         ClientBuilder::new()
     }
 
+    /// Create a clone of this client that uses the given default options for each request.
+    ///
+    /// The new client shares its connection pool and event loop with this client, along with its default headers and
+    /// middleware. Only the default options differ.
+    ///
+    /// ```rust
+    /// use chttp::{Client, Options};
+    /// use std::time::Duration;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let impatient_client = client.with_options(Options::default()
+    ///     .with_timeout(Some(Duration::from_secs(5))));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_options(&self, options: Options) -> Client {
+        // A mock answers requests the same way regardless of the options.
+        let transport = match self.agent.as_ref() {
+            Some(agent) => create_transport(agent.clone(), options.clone(), self.resolver.clone()),
+            None => self.transport.clone(),
+        };
+
+        Client {
+            transport: transport,
+            default_options: options,
+            ..self.clone()
+        }
+    }
+
+    /// Create a new builder that starts out with the configuration of this client, except for its middleware.
+    ///
+    /// Since middleware cannot be cloned, the builder has none, which makes this useful for creating a sibling client
+    /// with the same options, default headers and connection limits but its own middleware stack. Cookie handling is
+    /// implemented as middleware, so it is not carried over either. Clients built from the builder have their own
+    /// connection pool and event loop, unless this client's [`event_loop`](#method.event_loop) is given to the
+    /// builder.
+    ///
+    /// ```rust
+    /// use chttp::{Client, Options};
+    /// use std::time::Duration;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::builder()
+    ///     .options(Options::default().with_timeout(Some(Duration::from_secs(5))))
+    ///     .build()?;
+    /// let sibling = client.to_builder().build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_builder(&self) -> ClientBuilder {
+        ClientBuilder {
+            agent_config: self.agent_config.clone(),
+            default_headers: self.default_headers.clone(),
+            default_options: self.default_options.clone(),
+            user_agent: None,
+            middleware: Vec::new(),
+            resolver: self.resolver.clone(),
+            event_loop: None,
+            #[cfg(feature = "mock")]
+            mock: self.mock.clone(),
+        }
+    }
+
+    /// Shut down the client gracefully, waiting for requests that are in progress to complete.
+    ///
+    /// New requests are rejected with [`Error::Canceled`](../error/enum.Error.html#variant.Canceled) from the moment
+    /// this is called. The client's event loop then stops as soon as all active requests have completed, including
+    /// receiving their response bodies, or once the timeout has elapsed, whichever comes first. Any requests still
+    /// active at that point fail with [`Error::Timeout`](../error/enum.Error.html#variant.Timeout). This method blocks
+    /// until the event loop has stopped.
+    ///
+    /// Since clones of a client share the same event loop, this shuts down all clones as well, along with any other
+    /// clients sharing the event loop.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    /// use std::time::Duration;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// // ...
+    /// client.shutdown(Duration::from_secs(5));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shutdown(self, timeout: Duration) {
+        if let Some(agent) = self.agent {
+            agent.shutdown(timeout);
+        }
+    }
+
+    /// Get the event loop this client runs on, for sharing it with other clients.
+    ///
+    /// Returns `None` if the client answers requests using a mock, since no event loop is started for it. See
+    /// [`ClientBuilder::event_loop`](struct.ClientBuilder.html#method.event_loop) for details.
+    pub fn event_loop(&self) -> Option<EventLoop> {
+        self.agent.clone().map(|agent| EventLoop {
+            agent,
+        })
+    }
+
+    /// Get a snapshot of the usage statistics of this client, such as the number of requests sent.
+    ///
+    /// If the client shares its event loop with other clients, the statistics include their requests as well. Clients
+    /// using a mock have no event loop, and always report empty statistics.
+    pub fn metrics(&self) -> ClientMetrics {
+        self.agent.as_ref().map(agent::Handle::metrics).unwrap_or_default()
+    }
+
     /// Sends an HTTP GET request.
     ///
     /// The response body is provided as a stream that may only be consumed once.
@@ -139,12 +508,202 @@ impl Client {
         self.send(request)
     }
 
+    /// Sends an HTTP GET request and deserializes the JSON response body into a value of type `T`.
+    ///
+    /// If the server responds with a status code other than `2xx`, the body is not parsed, and
+    /// [`Error::Status`](../error/enum.Error.html#variant.Status) is returned instead.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    /// use std::collections::HashMap;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let headers: HashMap<String, String> = client.get_json("https://httpbin.org/headers")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn get_json<T, U>(&self, uri: U) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        http::Uri: http::HttpTryFrom<U>,
+    {
+        let request = http::Request::get(uri)
+            .header(http::header::ACCEPT, "application/json")
+            .body(Body::default())?;
+        let mut response = self.send(request)?;
+
+        if !response.status().is_success() {
+            return Err(Error::from_response(response));
+        }
+
+        response.body_mut().json()
+    }
+
     /// Sends an HTTP HEAD request.
     pub fn head<U>(&self, uri: U) -> Result<Response<Body>, Error> where http::Uri: http::HttpTryFrom<U> {
         let request = http::Request::head(uri).body(Body::default())?;
         self.send(request)
     }
 
+    /// Check whether a resource exists by sending an HTTP HEAD request.
+    ///
+    /// Returns `true` for a `2xx` response and `false` for `404 Not Found` or `410 Gone`. Any other status is returned
+    /// as [`Error::Status`](../error/enum.Error.html#variant.Status). Redirects are only followed if allowed by the
+    /// redirect policy of the request options, so with the default policy a redirect response is also an error.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// if client.exists("https://example.org/robots.txt")? {
+    ///     println!("found robots.txt");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn exists<U>(&self, uri: U) -> Result<bool, Error> where http::Uri: http::HttpTryFrom<U> {
+        let response = self.head(uri)?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            http::StatusCode::NOT_FOUND | http::StatusCode::GONE => Ok(false),
+            _ => Err(Error::from_response(response)),
+        }
+    }
+
+    /// Get the size of the resource at the given URI, as reported by the `Content-Length` header of a HEAD request.
+    ///
+    /// Returns `None` if the server does not report the size or reports an invalid or ambiguous size.
+    pub fn content_length<U>(&self, uri: U) -> Result<Option<u64>, Error> where http::Uri: http::HttpTryFrom<U> {
+        let response = self.head(uri)?;
+        let mut lengths = response.headers()
+            .get_all(http::header::CONTENT_LENGTH)
+            .iter()
+            .map(|value| value.to_str().ok().and_then(|value| value.trim().parse::<u64>().ok()));
+
+        // Multiple headers are only acceptable if they all agree.
+        let first = match lengths.next() {
+            Some(Some(len)) => len,
+            _ => return Ok(None),
+        };
+
+        if lengths.all(|len| len == Some(first)) {
+            Ok(Some(first))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Sends an HTTP GET request for part of a resource, from byte offset `start` to `end` inclusive, or to the end
+    /// of the resource if `end` is `None`.
+    ///
+    /// A successful response has the status `206 Partial Content` and a `Content-Range` header describing the part
+    /// of the resource included in the body. If the server ignores the range and responds with `200 OK` and the whole
+    /// resource, [`Error::RangeRequestUnsupported`](../error/enum.Error.html#variant.RangeRequestUnsupported) is
+    /// returned. Other responses, such as `416 Range Not Satisfiable`, are returned as-is.
+    ///
+    /// The response body is provided as a stream that may only be consumed once.
+    pub fn get_range<U>(&self, uri: U, start: u64, end: Option<u64>) -> Result<Response<Body>, Error> where http::Uri: http::HttpTryFrom<U> {
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+        let request = http::Request::get(uri)
+            .header(http::header::RANGE, range)
+            .body(Body::default())?;
+        let response = self.send(request)?;
+
+        if response.status() == http::StatusCode::OK {
+            return Err(Error::RangeRequestUnsupported);
+        }
+
+        Ok(response)
+    }
+
+    /// Download a resource to a file, resuming a previous download if the file already exists. Returns the size of the
+    /// complete file.
+    ///
+    /// If the file exists, only the rest of the resource starting at the current size of the file is requested, and
+    /// the received bytes are appended to the file. While a download is in progress, the `ETag` or `Last-Modified`
+    /// value of the resource is kept in a file next to the download, with `.resume` appended to its name. When
+    /// resuming, this is used to make sure that the remote resource has not changed since the download started. If it
+    /// has, or if the server does not support range requests, the whole resource is downloaded again from scratch.
+    ///
+    /// If the file exists but no `.resume` file is found, the existing contents are assumed to be the start of the
+    /// resource.
+    ///
+    /// ```rust
+    /// use chttp::Client;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let size = client.download_resume("https://example.org/big.iso", "big.iso")?;
+    /// println!("downloaded {} bytes", size);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn download_resume<U>(&self, uri: U, path: impl AsRef<Path>) -> Result<u64, Error> where http::Uri: http::HttpTryFrom<U> {
+        let uri: http::Uri = http::HttpTryFrom::try_from(uri).map_err(Into::<http::Error>::into)?;
+        self.download_resume_impl(uri, path.as_ref())
+    }
+
+    fn download_resume_impl(&self, uri: http::Uri, path: &Path) -> Result<u64, Error> {
+        let validator_path = resume_validator_path(path);
+        let offset = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+
+        let mut builder = http::Request::get(uri.clone());
+        if offset > 0 {
+            builder.header(http::header::RANGE, format!("bytes={}-", offset));
+
+            // Only resume if the resource is still the same one we started downloading.
+            if let Ok(validator) = fs::read_to_string(&validator_path) {
+                builder.header(http::header::IF_RANGE, validator.trim());
+            }
+        }
+
+        let mut response = self.send(builder.body(Body::default())?)?;
+        let (range_start, range_total) = content_range(&response);
+
+        let append = match response.status() {
+            http::StatusCode::PARTIAL_CONTENT if range_start == Some(offset) => true,
+            http::StatusCode::RANGE_NOT_SATISFIABLE if range_total == Some(offset) => {
+                // The file is already complete.
+                let _ = fs::remove_file(&validator_path);
+                return Ok(offset);
+            },
+            http::StatusCode::PARTIAL_CONTENT | http::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                // The existing file does not match the resource, so start over.
+                fs::remove_file(path)?;
+                let _ = fs::remove_file(&validator_path);
+                return self.download_resume_impl(uri, path);
+            },
+            status if status.is_success() => false,
+            _ => return Err(Error::from_response(response)),
+        };
+
+        // Remember which version of the resource is being downloaded, in case the download is interrupted.
+        match resume_validator(&response) {
+            Some(validator) => fs::write(&validator_path, validator)?,
+            None => {
+                let _ = fs::remove_file(&validator_path);
+            },
+        }
+
+        let mut file = if append {
+            OpenOptions::new().append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+
+        io::copy(response.body_mut(), &mut file)?;
+        let _ = fs::remove_file(&validator_path);
+
+        Ok(file.metadata()?.len())
+    }
+
     /// Sends an HTTP POST request.
     ///
     /// The response body is provided as a stream that may only be consumed once.
@@ -153,6 +712,26 @@ impl Client {
         self.send(request)
     }
 
+    /// Sends an HTTP POST request with a value serialized as JSON as the request body.
+    ///
+    /// The `Content-Type` header is set to `application/json`. If the value cannot be serialized,
+    /// [`Error::SerializationFailed`](../error/enum.Error.html#variant.SerializationFailed) is returned without
+    /// sending anything.
+    ///
+    /// The response body is provided as a stream that may only be consumed once.
+    #[cfg(feature = "json")]
+    pub fn post_json<T, U>(&self, uri: U, body: &T) -> Result<Response<Body>, Error>
+    where
+        T: serde::Serialize + ?Sized,
+        http::Uri: http::HttpTryFrom<U>,
+    {
+        let body = serde_json::to_vec(body).map_err(|e| Error::SerializationFailed(e.to_string()))?;
+        let request = http::Request::post(uri)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)?;
+        self.send(request)
+    }
+
     /// Sends an HTTP PUT request.
     ///
     /// The response body is provided as a stream that may only be consumed once.
@@ -161,6 +740,14 @@ impl Client {
         self.send(request)
     }
 
+    /// Sends an HTTP PATCH request.
+    ///
+    /// The response body is provided as a stream that may only be consumed once.
+    pub fn patch<U>(&self, uri: U, body: impl Into<Body>) -> Result<Response<Body>, Error> where http::Uri: http::HttpTryFrom<U> {
+        let request = http::Request::patch(uri).body(body)?;
+        self.send(request)
+    }
+
     /// Sends an HTTP DELETE request.
     ///
     /// The response body is provided as a stream that may only be consumed once.
@@ -180,6 +767,62 @@ impl Client {
         executor::block_on(self.send_async_impl(request))
     }
 
+    /// Sends a copy of a request and returns the response, leaving the original request untouched so that it can be
+    /// inspected or sent again.
+    ///
+    /// Only requests with a body that can be sent more than once can be copied, such as a body stored in memory. If
+    /// the request has a streaming body, [`Error::RequestBodyError`](../error/enum.Error.html#variant.RequestBodyError)
+    /// is returned without sending anything. Request extensions are not copied, except for
+    /// [`Options`](../options/struct.Options.html).
+    ///
+    /// ```rust
+    /// use chttp::{http, Body, Client};
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let request = http::Request::post("https://example.org").body(Body::from("hello"))?;
+    ///
+    /// let first = client.execute(&request)?;
+    /// let second = client.execute(&request)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute(&self, request: &crate::Request) -> Result<Response<Body>, Error> {
+        let request = internal::try_clone_request(request).ok_or_else(|| {
+            Error::RequestBodyError(Some(String::from("request body is a stream and cannot be sent more than once")))
+        })?;
+
+        self.send(request)
+    }
+
+    /// Sends a batch of requests concurrently and waits for all of them to complete.
+    ///
+    /// All of the requests are executed at the same time on the client's event loop, sharing its connection pool,
+    /// rather than one after another. The results are returned in the same order as the given requests.
+    ///
+    /// ```rust
+    /// use chttp::{http, Client};
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let requests = (1..=3).map(|i| {
+    ///     http::Request::get(format!("https://example.org/items/{}", i)).body(()).unwrap()
+    /// });
+    ///
+    /// for result in client.send_all(requests) {
+    ///     println!("{}", result?.status());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_all<B: Into<Body>>(&self, requests: impl IntoIterator<Item=Request<B>>) -> Vec<Result<Response<Body>, Error>> {
+        let futures = requests.into_iter()
+            .map(|request| self.send_async_impl(request))
+            .collect::<Vec<_>>();
+
+        executor::block_on(future::join_all(futures))
+    }
+
     /// Begin sending a request and return a future of the response.
     ///
     /// The request may include [extensions](../../http/struct.Extensions.html) to customize how it is sent. If the
@@ -187,22 +830,58 @@ impl Client {
     /// instead of the default options this client is configured with.
     ///
     /// The response body is provided as a stream that may only be consumed once.
+    ///
+    /// The returned future is a standard library future, so it can be `.await`ed inside of an async function or block
+    /// and driven by any executor:
+    ///
+    /// ```rust
+    /// use chttp::{http, Client};
+    ///
+    /// # async fn run() -> Result<(), chttp::Error> {
+    /// let client = Client::new()?;
+    /// let request = http::Request::get("https://example.org").body(())?;
+    /// let response = client.send_async(request).await?;
+    /// println!("{}", response.status());
+    /// # Ok(())
+    /// # }
+    /// ```
     #[cfg(feature = "async-api")]
-    pub fn send_async<B: Into<Body>>(&self, request: Request<B>) -> impl Future<Item=Response<Body>, Error=Error> {
+    pub fn send_async<B: Into<Body>>(&self, request: Request<B>) -> ResponseFuture {
         self.send_async_impl(request)
     }
 
-    fn send_async_impl<B: Into<Body>>(&self, request: Request<B>) -> impl Future<Output=Result<Response<Body>, Error>> {
+    fn send_async_impl<B: Into<Body>>(&self, request: Request<B>) -> ResponseFuture {
         let mut request = request.map(Into::into);
 
+        // Add default headers that the request does not already have.
+        for name in self.default_headers.keys() {
+            if !request.headers().contains_key(name) {
+                for value in self.default_headers.get_all(name) {
+                    request.headers_mut().append(name, value.clone());
+                }
+            }
+        }
+
         // Set default user agent if not specified.
         request.headers_mut()
             .entry(http::header::USER_AGENT)
             .unwrap()
             .or_insert(USER_AGENT.parse().unwrap());
 
+        // Set a default content type if the body knows what it contains.
+        if let Some(content_type) = request.body().content_type().cloned() {
+            request.headers_mut()
+                .entry(http::header::CONTENT_TYPE)
+                .unwrap()
+                .or_insert(content_type);
+        }
+
         let uri = request.uri().clone();
 
+        // Allow the request to be canceled through the returned future.
+        let cancellation = request::Cancellation::default();
+        request.extensions_mut().insert(cancellation.clone());
+
         let middleware = self.middleware.clone();
 
         // Apply any request middleware, starting with the outermost one.
@@ -210,25 +889,138 @@ impl Client {
             request = middleware.filter_request(request);
         }
 
-        // Extract the request options, or use the default options.
-        let options = request.extensions_mut().remove::<Options>();
-        let options = options.as_ref().unwrap_or(&self.default_options);
+        // Options in the request take precedence over the default options of the client. They are read after the
+        // request middleware has run, since middleware may set options of its own.
+        let fail_on_error = request.extensions()
+            .get::<Options>()
+            .unwrap_or(&self.default_options)
+            .fail_on_error;
 
-        return request::create(request, options)
-            .and_then(|(request, future)| {
-                self.agent.begin_execute(request).map(|_| future)
-            })
-            .into_future()
-            .flatten()
-            .map(move |mut response| {
+        // Send the request through any middleware that want to intercept it.
+        let inner = Next::new(middleware.clone(), self.transport.clone())
+            .send(request)
+            .map(move |result| result.map(|mut response| {
                 response.extensions_mut().insert(uri);
 
                 // Apply response middleware, starting with the innermost one.
                 for middleware in middleware.iter() {
+                    response = response.map(|body| middleware.filter_body(body));
                     response = middleware.filter_response(response);
                 }
 
                 response
-            });
+            }))
+            .and_then(move |response| async move {
+                let status = response.status();
+
+                if fail_on_error && (status.is_client_error() || status.is_server_error()) {
+                    Err(Error::from_response_async(response).await)
+                } else {
+                    Ok(response)
+                }
+            })
+            .boxed();
+
+        ResponseFuture {
+            inner,
+            cancellation,
+        }
+    }
+}
+
+/// Get the path of the file that stores the validator of a partially downloaded file.
+fn resume_validator_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".resume");
+    PathBuf::from(name)
+}
+
+/// Get a value identifying the version of a resource that can be used in an `If-Range` header.
+///
+/// Weak entity tags cannot be used for range requests, so the modification date is used instead in that case.
+fn resume_validator(response: &Response<Body>) -> Option<&str> {
+    let headers = response.headers();
+
+    headers.get(http::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get(http::header::LAST_MODIFIED).and_then(|value| value.to_str().ok()))
+}
+
+/// Get the first byte offset and the total length from the `Content-Range` header of a response, if present.
+fn content_range(response: &Response<Body>) -> (Option<u64>, Option<u64>) {
+    let range = match response.headers().get(http::header::CONTENT_RANGE).and_then(|value| value.to_str().ok()) {
+        Some(value) if value.starts_with("bytes ") => &value[6..],
+        _ => return (None, None),
+    };
+
+    let mut parts = range.splitn(2, '/');
+    let start = parts.next()
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.trim().parse().ok());
+    let total = parts.next().and_then(|total| total.trim().parse().ok());
+
+    (start, total)
+}
+
+/// A future for a response to a request sent by a [`Client`](struct.Client.html).
+///
+/// The future resolves once the response headers have been received, while the response body continues to be received
+/// as it is read. Dropping the future before it completes cancels the request. To cancel a request from elsewhere, such
+/// as after the future has been handed off to an executor, use a [`RequestHandle`](struct.RequestHandle.html).
+pub struct ResponseFuture {
+    inner: BoxFuture<'static, Result<Response<Body>, Error>>,
+    cancellation: request::Cancellation,
+}
+
+impl ResponseFuture {
+    /// Get a handle that can be used to cancel the request.
+    pub fn handle(&self) -> RequestHandle {
+        RequestHandle {
+            cancellation: self.cancellation.clone(),
+        }
+    }
+}
+
+impl Future for ResponseFuture {
+    type Output = Result<Response<Body>, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.cancellation.register(cx.waker());
+
+        if self.cancellation.is_canceled() {
+            return Poll::Ready(Err(Error::Canceled));
+        }
+
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for ResponseFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ResponseFuture")
+    }
+}
+
+/// A handle for canceling a request sent asynchronously, obtained from
+/// [`ResponseFuture::handle`](struct.ResponseFuture.html#method.handle).
+///
+/// Canceling a request aborts any transfer in progress for it. If the response future has not completed yet, it
+/// resolves with [`Error::Canceled`](../error/enum.Error.html#variant.Canceled). If the response has already been
+/// received, further reads from the response body fail instead. Dropping a handle does not cancel the request.
+#[derive(Clone, Debug)]
+pub struct RequestHandle {
+    cancellation: request::Cancellation,
+}
+
+impl RequestHandle {
+    /// Cancel the request.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Check if the request has been canceled.
+    pub fn is_canceled(&self) -> bool {
+        self.cancellation.is_canceled()
     }
 }
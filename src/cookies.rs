@@ -1,18 +1,31 @@
 //! Cookie state management.
 //!
 //! This module provides a cookie jar implementation conforming to RFC 6265.
+//!
+//! Cookie jars can be saved to and loaded from files in the Netscape cookie file format, which is the same format used
+//! by curl's `--cookie` and `--cookie-jar` options.
 
 use chrono::Duration;
 use chrono::prelude::*;
-use crate::{Request, Response};
-use crate::middleware::Middleware;
+use crate::{Error, Request, Response};
+use crate::middleware::{Middleware, Next};
+use futures::future::BoxFuture;
+use futures::prelude::*;
 use http::Uri;
 use log::*;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::net::{Ipv4Addr, Ipv6Addr};
-use std::sync::RwLock;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 
 /// Information stored about an HTTP cookie.
+///
+/// Cookies received from servers can be inspected using [`CookieJar::cookies_for`](struct.CookieJar.html#method.cookies_for).
+/// New cookies can be created with [`Cookie::new`](#method.new) and added to a jar using
+/// [`CookieJar::set`](struct.CookieJar.html#method.set).
+#[derive(Clone, Debug)]
 pub struct Cookie {
     /// The name of the cookie.
     name: String,
@@ -24,6 +37,8 @@ pub struct Cookie {
     path: String,
     /// True if the cookie is marked as secure (limited in scope to HTTPS).
     secure: bool,
+    /// True if the cookie is marked as HTTP only (not accessible to scripts in a browser).
+    http_only: bool,
     /// True if the cookie is a host-only cookie (i.e. the request's host must exactly match the domain of the cookie).
     host_only: bool,
     /// Time when this cookie expires. If not present, then this is a session cookie that expires when the current
@@ -32,6 +47,93 @@ pub struct Cookie {
 }
 
 impl Cookie {
+    /// Create a new session cookie with the given name and value.
+    ///
+    /// Unless a domain or path is set, they are determined by the URI the cookie is set for.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            domain: String::new(),
+            path: String::new(),
+            secure: false,
+            http_only: false,
+            host_only: true,
+            expiration: None,
+        }
+    }
+
+    /// Set the domain of the cookie. The cookie will also be sent to all subdomains of the domain.
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = domain.into().trim_start_matches('.').to_lowercase();
+        self.host_only = self.domain.is_empty();
+        self
+    }
+
+    /// Set the path prefix of the cookie.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Set whether the cookie should only be sent over HTTPS.
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set whether the cookie is marked as HTTP only.
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the time when the cookie expires. If `None`, the cookie is a session cookie.
+    pub fn with_expiration(mut self, expiration: Option<DateTime<Utc>>) -> Self {
+        self.expiration = expiration;
+        self
+    }
+
+    /// Get the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Get the domain the cookie belongs to.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Get the path prefix the cookie belongs to.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the time when the cookie expires, or `None` if this is a session cookie.
+    pub fn expiration(&self) -> Option<DateTime<Utc>> {
+        self.expiration
+    }
+
+    /// Check if the cookie is only sent over HTTPS.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Check if the cookie is marked as HTTP only.
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// Check if the cookie is only sent to the exact host it belongs to, and not to its subdomains.
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
     /// Parse a cookie from a Set-Cookie header value, within the context of the given URI.
     fn parse(header: &str, uri: &Uri) -> Option<Self> {
         let mut attributes = header.split(";")
@@ -42,51 +144,57 @@ impl Cookie {
 
         let mut first_pair = attributes.next()?;
 
-        let cookie_name = first_pair.next()?.into();
-        let cookie_value = first_pair.next()?.into();
-        let mut cookie_domain = None;
-        let mut cookie_path = None;
-        let mut cookie_secure = false;
-        let mut cookie_expiration = None;
+        let mut cookie = Cookie::new(first_pair.next()?, first_pair.next()?);
 
         // Look for known attribute names and parse them. Note that there are
         // multiple attributes in the spec that we don't parse right now because
-        // we do not care about them, including HttpOnly and SameSite.
+        // we do not care about them, such as SameSite.
         for mut attribute in attributes {
             let name = attribute.next()?;
             let value = attribute.next();
 
             if name.eq_ignore_ascii_case("Expires") {
-                if cookie_expiration.is_none() {
+                if cookie.expiration.is_none() {
                     if let Some(value) = value {
                         if let Ok(time) = DateTime::parse_from_rfc2822(value) {
-                            cookie_expiration = Some(time.with_timezone(&Utc));
+                            cookie.expiration = Some(time.with_timezone(&Utc));
                         }
                     }
                 }
             } else if name.eq_ignore_ascii_case("Domain") {
-                cookie_domain = value
-                    .map(|s| s.trim_start_matches("."))
-                    .map(str::to_lowercase);
+                if let Some(value) = value {
+                    cookie = cookie.with_domain(value);
+                }
             } else if name.eq_ignore_ascii_case("Max-Age") {
                 if let Some(value) = value {
                     if let Ok(seconds) = value.parse() {
-                        cookie_expiration = Some(Utc::now() + Duration::seconds(seconds));
+                        cookie.expiration = Some(Utc::now() + Duration::seconds(seconds));
                     }
                 }
             } else if name.eq_ignore_ascii_case("Path") {
-                cookie_path = value.map(ToOwned::to_owned);
+                if let Some(value) = value {
+                    cookie.path = value.to_owned();
+                }
             } else if name.eq_ignore_ascii_case("Secure") {
-                cookie_secure = true;
+                cookie.secure = true;
+            } else if name.eq_ignore_ascii_case("HttpOnly") {
+                cookie.http_only = true;
             }
         }
 
-        // Perform some validations on the domain.
-        if let Some(domain) = cookie_domain.as_ref() {
+        cookie.scope_to(uri)
+    }
+
+    /// Fill in the domain and path of a cookie being set for the given URI if they are not set, and check that the URI
+    /// is allowed to set the cookie.
+    fn scope_to(mut self, uri: &Uri) -> Option<Self> {
+        if self.host_only {
+            self.domain = uri.host()?.to_owned();
+        } else {
             // The given domain must domain-match the origin.
             // https://tools.ietf.org/html/rfc6265#section-5.3.6
-            if !Cookie::domain_matches(uri.host()?, domain) {
-                warn!("cookie '{}' dropped, domain '{}' not allowed to set cookies for '{}'", cookie_name, uri.host()?, domain);
+            if !Cookie::domain_matches(uri.host()?, &self.domain) {
+                warn!("cookie '{}' dropped, domain '{}' not allowed to set cookies for '{}'", self.name, uri.host()?, self.domain);
                 return None;
             }
 
@@ -96,30 +204,75 @@ impl Cookie {
                 use ::psl::Psl;
                 let list = ::psl::List::new();
 
-                if let Some(suffix) = list.suffix(domain) {
-                    if domain == suffix.to_str() {
-                        warn!("cookie '{}' dropped, setting cookies for domain '{}' is not allowed", cookie_name, domain);
+                if let Some(suffix) = list.suffix(&self.domain) {
+                    if self.domain == suffix.to_str() {
+                        warn!("cookie '{}' dropped, setting cookies for domain '{}' is not allowed", self.name, self.domain);
                         return None;
                     }
                 }
             }
         }
 
+        if self.path.is_empty() {
+            self.path = Cookie::default_path(uri).to_owned();
+        }
+
+        Some(self)
+    }
+
+    /// Parse a cookie from a line of a Netscape cookie file.
+    fn parse_netscape(line: &str) -> Option<Self> {
+        // HttpOnly cookies are written with a special prefix that looks like a comment.
+        let http_only = line.starts_with("#HttpOnly_");
+        let line = line.trim_start_matches("#HttpOnly_");
+        let mut fields = line.split('\t');
+
+        let domain = fields.next()?;
+        let include_subdomains = fields.next()? == "TRUE";
+        let path = fields.next()?;
+        let secure = fields.next()? == "TRUE";
+        let expiration = match fields.next()?.parse::<i64>().ok()? {
+            0 => None,
+            // An expiration outside of the supported range makes the whole line invalid, instead of panicking.
+            seconds => Some(Utc.timestamp_opt(seconds, 0).single()?),
+        };
+        let name = fields.next()?;
+        let value = fields.next().unwrap_or("");
+
         Some(Self {
-            name: cookie_name,
-            value: cookie_value,
-            secure: cookie_secure,
-            expiration: cookie_expiration,
-            host_only: cookie_domain.is_none(),
-            domain: cookie_domain.or_else(|| {
-                uri.host().map(ToOwned::to_owned)
-            })?,
-            path: cookie_path.unwrap_or_else(|| {
-                Cookie::default_path(uri).to_owned()
-            }),
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: domain.trim_start_matches('.').to_lowercase(),
+            path: path.to_owned(),
+            secure,
+            http_only,
+            host_only: !include_subdomains,
+            expiration,
         })
     }
 
+    /// Format this cookie as a line of a Netscape cookie file.
+    fn to_netscape(&self) -> String {
+        let bool_str = |b| if b {
+            "TRUE"
+        } else {
+            "FALSE"
+        };
+
+        format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { "#HttpOnly_" } else { "" },
+            if self.host_only { "" } else { "." },
+            self.domain,
+            bool_str(!self.host_only),
+            self.path,
+            bool_str(self.secure),
+            self.expiration.map(|time| time.timestamp()).unwrap_or(0),
+            self.name,
+            self.value,
+        )
+    }
+
     fn is_expired(&self) -> bool {
         match self.expiration {
             Some(time) => time < Utc::now(),
@@ -210,13 +363,104 @@ impl Cookie {
 }
 
 /// Provides automatic cookie session management using an in-memory cookie store.
-#[derive(Default)]
+///
+/// A cookie jar can also be attached to an individual request as an extension, in which case it is used for that
+/// request in place of the client's cookie jar. Cookies are only read from and stored into the attached jar, which
+/// makes it possible to send a request with a specific set of cookies without affecting the client's cookies. This
+/// requires the client to have cookies enabled:
+///
+/// ```rust
+/// use chttp::{http, Client};
+/// use chttp::cookies::{Cookie, CookieJar};
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let client = Client::builder().with_cookies().build()?;
+///
+/// let uri: http::Uri = "https://example.org".parse().unwrap();
+/// let jar = CookieJar::default();
+/// jar.set(&uri, Cookie::new("session", "abc123"));
+///
+/// let request = http::Request::get(uri)
+///     .extension(jar.clone())
+///     .body(())?;
+/// let response = client.send(request)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Cloning a cookie jar produces a new handle to the same underlying cookie store, so a jar can be given to a client
+/// while keeping a handle to it for saving it later:
+///
+/// ```rust
+/// use chttp::Client;
+/// use chttp::cookies::CookieJar;
+///
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let jar = CookieJar::load("cookies.txt")?;
+/// let client = Client::builder()
+///     .cookie_jar(jar.clone())
+///     .build()?;
+///
+/// client.get("https://example.org")?;
+/// jar.save("cookies.txt")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
 pub struct CookieJar {
     /// A map of cookies indexed by a string of the format `{domain}.{path}.{name}`.
-    cookies: RwLock<HashMap<String, Cookie>>,
+    cookies: Arc<RwLock<HashMap<String, Cookie>>>,
 }
 
 impl CookieJar {
+    /// Load a cookie jar from a file in the Netscape cookie file format.
+    ///
+    /// Lines that cannot be parsed and cookies that have already expired are skipped.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let jar = Self::default();
+        let file = BufReader::new(File::open(path)?);
+        let mut cookies = Vec::new();
+
+        for line in file.lines() {
+            let line = line?;
+
+            if line.trim().is_empty() || (line.starts_with('#') && !line.starts_with("#HttpOnly_")) {
+                continue;
+            }
+
+            match Cookie::parse_netscape(&line) {
+                Some(cookie) => cookies.push(cookie),
+                None => warn!("skipping malformed line in cookie file"),
+            }
+        }
+
+        jar.add(cookies.into_iter());
+
+        Ok(jar)
+    }
+
+    /// Save all cookies in this jar to a file in the Netscape cookie file format, replacing the file if it exists.
+    ///
+    /// Expired cookies are not saved. Session cookies are saved without an expiration time.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let jar = self.cookies.read().unwrap();
+        let mut file = BufWriter::new(File::create(path)?);
+
+        writeln!(file, "# Netscape HTTP Cookie File")?;
+
+        let mut lines = jar.values()
+            .filter(|cookie| !cookie.is_expired())
+            .map(Cookie::to_netscape)
+            .collect::<Vec<_>>();
+        lines.sort();
+
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+
+        file.flush()
+    }
+
     /// Add all the cookies in the given iterator to the cookie jar.
     pub fn add(&self, cookies: impl Iterator<Item=Cookie>) {
         let mut jar = self.cookies.write().unwrap();
@@ -231,6 +475,33 @@ impl CookieJar {
         });
     }
 
+    /// Get all cookies in the jar that would be sent with a request to the given URI.
+    pub fn cookies_for(&self, uri: &Uri) -> Vec<Cookie> {
+        let jar = self.cookies.read().unwrap();
+
+        let mut cookies: Vec<Cookie> = jar.values()
+            .filter(|cookie| cookie.matches(uri))
+            .cloned()
+            .collect();
+
+        cookies.sort_by(|a, b| a.name.cmp(&b.name));
+        cookies
+    }
+
+    /// Add a cookie to the jar as if it was set by a response from the given URI.
+    ///
+    /// If the cookie does not have a domain or path set, they are determined from the URI. Returns `false` if the URI
+    /// is not allowed to set the cookie, such as if the cookie's domain does not match the URI or is a public suffix.
+    pub fn set(&self, uri: &Uri, cookie: Cookie) -> bool {
+        match cookie.scope_to(uri) {
+            Some(cookie) => {
+                self.add(Some(cookie).into_iter());
+                true
+            },
+            None => false,
+        }
+    }
+
     fn get_cookies(&self, uri: &Uri) -> Option<String> {
         let jar = self.cookies.read().unwrap();
 
@@ -253,13 +524,30 @@ impl CookieJar {
 
 impl Middleware for CookieJar {
     fn filter_request(&self, mut request: Request) -> Request {
-        if let Some(header) = self.get_cookies(request.uri()) {
+        // A cookie jar attached to the request takes the place of this one.
+        let jar = request.extensions().get::<CookieJar>().unwrap_or(self);
+
+        if let Some(header) = jar.get_cookies(request.uri()) {
             request.headers_mut().insert(http::header::COOKIE, header.parse().unwrap());
         }
 
         request
     }
 
+    /// Carries a cookie jar attached to the request over to the response, so that cookies set by the response are
+    /// stored in it.
+    fn send(&self, mut request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        match request.extensions_mut().remove::<CookieJar>() {
+            Some(jar) => next.send(request)
+                .map(move |result| result.map(|mut response| {
+                    response.extensions_mut().insert(jar);
+                    response
+                }))
+                .boxed(),
+            None => next.send(request),
+        }
+    }
+
     /// Extracts cookies set via the Set-Cookie header.
     fn filter_response(&self, response: Response) -> Response {
         if response.headers().contains_key(http::header::SET_COOKIE) {
@@ -277,7 +565,8 @@ impl Middleware for CookieJar {
                     None
                 });
 
-            self.add(cookies);
+            // Store the cookies in the jar attached to the request, if any.
+            response.extensions().get::<CookieJar>().unwrap_or(self).add(cookies);
         }
 
         response
@@ -377,6 +666,92 @@ mod tests {
         assert_eq!(request.headers()[http::header::COOKIE], "baz=123; foo=bar");
     }
 
+    #[test]
+    fn netscape_format_roundtrip() {
+        let uri: Uri = "https://example.com/foo".parse().unwrap();
+
+        for header in &[
+            "foo=bar",
+            "foo=bar; domain=example.com; path=/; secure",
+            "foo=bar; expires=Wed, 21 Oct 2099 07:28:00 GMT",
+        ] {
+            let cookie = Cookie::parse(header, &uri).unwrap();
+            let parsed = Cookie::parse_netscape(&cookie.to_netscape()).unwrap();
+
+            assert_eq!(parsed.key(), cookie.key());
+            assert_eq!(parsed.value, cookie.value);
+            assert_eq!(parsed.secure, cookie.secure);
+            assert_eq!(parsed.host_only, cookie.host_only);
+            assert_eq!(parsed.expiration, cookie.expiration);
+        }
+    }
+
+    #[test]
+    fn parse_curl_cookie_file_line() {
+        let cookie = Cookie::parse_netscape(".example.com\tTRUE\t/\tFALSE\t0\tsession\t123").unwrap();
+
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/");
+        assert!(!cookie.secure);
+        assert_eq!(cookie.expiration, None);
+        assert_eq!(cookie.name, "session");
+        assert_eq!(cookie.value, "123");
+    }
+
+    #[test]
+    fn parse_cookie_file_line_with_out_of_range_expiration() {
+        assert!(Cookie::parse_netscape(".example.com\tTRUE\t/\tFALSE\t9223372036854775807\tsession\t123").is_none());
+    }
+
+    #[test]
+    fn set_and_inspect_cookies() {
+        let uri: Uri = "https://example.com/foo".parse().unwrap();
+        let jar = CookieJar::default();
+
+        assert!(jar.set(&uri, Cookie::new("session", "abc").with_http_only(true)));
+        assert!(!jar.set(&uri, Cookie::new("other", "123").with_domain("example.org")));
+
+        let cookies = jar.cookies_for(&uri);
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].name(), "session");
+        assert_eq!(cookies[0].value(), "abc");
+        assert_eq!(cookies[0].domain(), "example.com");
+        assert_eq!(cookies[0].path(), "/");
+        assert!(cookies[0].is_http_only());
+        assert!(cookies[0].is_host_only());
+
+        assert!(jar.cookies_for(&"https://www.example.com".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn request_cookie_jar_overrides_shared_jar() {
+        let uri: Uri = "https://example.com/foo".parse().unwrap();
+        let shared = CookieJar::default();
+        let jar = CookieJar::default();
+
+        shared.set(&uri, Cookie::new("shared", "1"));
+        jar.set(&uri, Cookie::new("override", "2"));
+
+        let request = shared.filter_request(http::Request::builder()
+            .uri(uri.clone())
+            .extension(jar.clone())
+            .body(crate::Body::default())
+            .unwrap());
+
+        assert_eq!(request.headers()[http::header::COOKIE], "override=2");
+
+        shared.filter_response(http::Response::builder()
+            .header(http::header::SET_COOKIE, "new=3")
+            .extension(uri.clone())
+            .extension(jar.clone())
+            .body(crate::Body::default())
+            .unwrap());
+
+        assert_eq!(shared.get_cookies(&uri).unwrap(), "shared=1");
+        assert_eq!(jar.get_cookies(&uri).unwrap(), "new=3; override=2");
+    }
+
     #[test]
     fn expire_a_cookie() {
         let uri: Uri = "https://example.com/foo".parse().unwrap();
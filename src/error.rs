@@ -1,17 +1,33 @@
 //! Types for error handling.
 
+use crate::Response;
+use futures::future;
+use http::{HeaderMap, StatusCode};
 use std::error::Error as StdError;
 use std::fmt;
-use std::io;
+use std::io::{self, Read};
+
+/// The maximum number of bytes of a response body to keep in a [`Status`](enum.Error.html#variant.Status) error.
+const STATUS_BODY_LIMIT: u64 = 64 * 1024;
 
 
 /// All possible types of errors that can be returned from cHTTP.
+///
+/// Failures reported by curl are mapped to the most specific variant available, such as
+/// [`CouldntResolveHost`](#variant.CouldntResolveHost), [`ConnectFailed`](#variant.ConnectFailed),
+/// [`SSLConnectFailed`](#variant.SSLConnectFailed), [`Timeout`](#variant.Timeout), or
+/// [`TooManyRedirects`](#variant.TooManyRedirects), so that callers can decide how to react to different kinds of
+/// failures. Errors without a more specific variant are reported as [`Curl`](#variant.Curl).
 #[derive(Debug)]
 pub enum Error {
+    /// The global client has already been created, so its configuration can no longer be changed.
+    AlreadyInitialized,
     /// A problem occurred with the local certificate.
     BadClientCertificate(Option<String>),
     /// The server certificate could not be validated.
     BadServerCertificate(Option<String>),
+    /// Failed to bind the local end of the connection to the configured interface or local port.
+    BindFailed(Option<String>),
     /// The request was canceled before it could be completed.
     Canceled,
     /// Failed to connect to the server.
@@ -38,12 +54,30 @@ pub enum Error {
     Io(io::Error),
     /// The server did not send a response.
     NoResponse,
+    /// The public key of the server did not match the pinned public key.
+    PinnedPublicKeyMismatch,
     /// The server does not support or accept range requests.
     RangeRequestUnsupported,
     /// An error occurred while writing the request body.
     RequestBodyError(Option<String>),
     /// An error occurred while reading the response body.
     ResponseBodyError(Option<String>),
+    /// The response body exceeded the configured maximum response size.
+    ResponseTooLarge,
+    /// A value could not be serialized into a request body.
+    SerializationFailed(String),
+    /// The server responded with a status code indicating that the request was not successful.
+    ///
+    /// The error includes the headers of the response and the beginning of its body, which often describe what went
+    /// wrong.
+    Status {
+        /// The status code of the response.
+        code: StatusCode,
+        /// The headers of the response.
+        headers: HeaderMap,
+        /// The response body, truncated to at most 64 KiB.
+        body: Vec<u8>,
+    },
     /// Failed to connect over a secure socket.
     SSLConnectFailed(Option<String>),
     /// An error ocurred in the secure socket engine.
@@ -56,17 +90,130 @@ pub enum Error {
     TooManyRedirects,
 }
 
+impl Error {
+    /// Create a [`Status`](#variant.Status) error from an unsuccessful response, reading the beginning of its body.
+    pub(crate) fn from_response(response: Response) -> Error {
+        let (parts, body) = response.into_parts();
+        let mut buffer = Vec::new();
+
+        // The body is only informational, so a failure to read it should not hide the status.
+        if let Err(e) = body.take(STATUS_BODY_LIMIT).read_to_end(&mut buffer) {
+            log::debug!("failed to read body of unsuccessful response: {}", e);
+        }
+
+        Error::Status {
+            code: parts.status,
+            headers: parts.headers,
+            body: buffer,
+        }
+    }
+
+    /// Create a [`Status`](#variant.Status) error from an unsuccessful response like
+    /// [`from_response`](#method.from_response), but without blocking while reading the body, for use inside of a
+    /// future.
+    pub(crate) async fn from_response_async(response: Response) -> Error {
+        let (parts, mut body) = response.into_parts();
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 8192];
+
+        while buffer.len() < STATUS_BODY_LIMIT as usize {
+            let len = chunk.len().min(STATUS_BODY_LIMIT as usize - buffer.len());
+
+            match future::poll_fn(|cx| body.poll_read_nonblocking(cx, &mut chunk[..len])).await {
+                Ok(0) => break,
+                Ok(amount) => buffer.extend_from_slice(&chunk[..amount]),
+                Err(e) => {
+                    log::debug!("failed to read body of unsuccessful response: {}", e);
+                    break;
+                },
+            }
+        }
+
+        Error::Status {
+            code: parts.status,
+            headers: parts.headers,
+            body: buffer,
+        }
+    }
+
+    /// Get the status code of the response if the error was caused by an unsuccessful response.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the error was caused by a request taking longer than the configured timeout.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::Timeout => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error was caused by a failure to resolve or connect to the server or proxy, or to bind the
+    /// local end of the connection.
+    pub fn is_connect(&self) -> bool {
+        match self {
+            Error::BindFailed(_)
+                | Error::ConnectFailed
+                | Error::CouldntResolveHost
+                | Error::CouldntResolveProxy => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error was caused by a problem with SSL/TLS, such as an invalid certificate or a failed
+    /// handshake.
+    pub fn is_tls(&self) -> bool {
+        match self {
+            Error::BadClientCertificate(_)
+                | Error::BadServerCertificate(_)
+                | Error::PinnedPublicKeyMismatch
+                | Error::SSLConnectFailed(_)
+                | Error::SSLEngineError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error was caused by a response with a client error status code (400-499).
+    pub fn is_client_error(&self) -> bool {
+        match self {
+            Error::Status { code, .. } => code.is_client_error(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error was caused by a response with a server error status code (500-599).
+    pub fn is_server_error(&self) -> bool {
+        match self {
+            Error::Status { code, .. } => code.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}: {}", self, Error::description(self))
+        match self {
+            // Leave out the headers and body, which are too long to be useful here.
+            Error::Status { code, .. } => write!(f, "Status({}): {}", code, Error::description(self)),
+            _ => write!(f, "{:?}: {}", self, Error::description(self)),
+        }
     }
 }
 
 impl StdError for Error {
     fn description(&self) -> &str {
         match self {
+            &Error::AlreadyInitialized => "global client is already initialized",
             &Error::BadClientCertificate(Some(ref e)) => e,
+            &Error::BadClientCertificate(None) => "problem with the local client certificate",
             &Error::BadServerCertificate(Some(ref e)) => e,
+            &Error::BadServerCertificate(None) => "server certificate could not be validated",
+            &Error::BindFailed(Some(ref e)) => e,
+            &Error::BindFailed(None) => "failed to bind the local end of the connection",
             &Error::ConnectFailed => "failed to connect to the server",
             &Error::CouldntResolveHost => "couldn't resolve host name",
             &Error::CouldntResolveProxy => "couldn't resolve proxy host name",
@@ -79,10 +226,15 @@ impl StdError for Error {
             &Error::InvalidUtf8 => "bytes are not valid UTF-8",
             &Error::Io(ref e) => e.description(),
             &Error::NoResponse => "server did not send a response",
+            &Error::PinnedPublicKeyMismatch => "server public key does not match the pinned public key",
             &Error::RangeRequestUnsupported => "server does not support or accept range requests",
             &Error::RequestBodyError(Some(ref e)) => e,
             &Error::ResponseBodyError(Some(ref e)) => e,
+            &Error::ResponseTooLarge => "response body exceeded the maximum allowed size",
+            &Error::SerializationFailed(ref e) => e,
+            &Error::Status { .. } => "server responded with an unsuccessful status code",
             &Error::SSLConnectFailed(Some(ref e)) => e,
+            &Error::SSLConnectFailed(None) => "failed to establish a secure connection",
             &Error::SSLEngineError(Some(ref e)) => e,
             &Error::Timeout => "request took longer than the configured timeout",
             &Error::TooManyConnections => "max connection limit exceeded",
@@ -91,7 +243,7 @@ impl StdError for Error {
         }
     }
 
-    fn cause(&self) -> Option<&dyn StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             &Error::InvalidHttpFormat(ref e) => Some(e),
             &Error::Io(ref e) => Some(e),
@@ -102,10 +254,14 @@ impl StdError for Error {
 
 impl From<curl::Error> for Error {
     fn from(error: curl::Error) -> Error {
-        if error.is_ssl_certproblem() || error.is_ssl_cacert_badfile() {
+        if error.code() == curl_sys::CURLE_SSL_PINNEDPUBKEYNOTMATCH {
+            Error::PinnedPublicKeyMismatch
+        } else if error.is_ssl_certproblem() || error.is_ssl_cacert_badfile() {
             Error::BadClientCertificate(error.extra_description().map(str::to_owned))
         } else if error.is_peer_failed_verification() || error.is_ssl_cacert() {
             Error::BadServerCertificate(error.extra_description().map(str::to_owned))
+        } else if error.is_interface_failed() {
+            Error::BindFailed(error.extra_description().map(str::to_owned))
         } else if error.is_couldnt_connect() {
             Error::ConnectFailed
         } else if error.is_couldnt_resolve_host() {
@@ -122,9 +278,11 @@ impl From<curl::Error> for Error {
             Error::RangeRequestUnsupported
         } else if error.is_read_error() || error.is_aborted_by_callback() {
             Error::RequestBodyError(error.extra_description().map(str::to_owned))
+        } else if error.is_filesize_exceeded() {
+            Error::ResponseTooLarge
         } else if error.is_write_error() || error.is_partial_file() {
             Error::ResponseBodyError(error.extra_description().map(str::to_owned))
-        } else if error.is_ssl_connect_error() {
+        } else if error.is_ssl_connect_error() || error.is_ssl_cipher() {
             Error::SSLConnectFailed(error.extra_description().map(str::to_owned))
         } else if error.is_ssl_engine_initfailed() || error.is_ssl_engine_notfound() || error.is_ssl_engine_setfailed() {
             Error::SSLEngineError(error.extra_description().map(str::to_owned))
@@ -184,11 +342,44 @@ impl From<std::str::Utf8Error> for Error {
 }
 
 #[cfg(feature = "json")]
-impl From<json::Error> for Error {
-    fn from(error: json::Error) -> Error {
-        match error {
-            json::Error::FailedUtf8Parsing => Error::InvalidUtf8,
-            _ => Error::InvalidJson,
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        if error.is_io() {
+            io::Error::from(error).into()
+        } else {
+            Error::InvalidJson
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_error(code: u16) -> Error {
+        Error::Status {
+            code: StatusCode::from_u16(code).unwrap(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn classify_status_errors() {
+        assert!(status_error(404).is_client_error());
+        assert!(!status_error(404).is_server_error());
+        assert!(status_error(503).is_server_error());
+        assert!(!status_error(503).is_client_error());
+        assert_eq!(status_error(503).status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn classify_other_errors() {
+        assert!(Error::Timeout.is_timeout());
+        assert!(Error::ConnectFailed.is_connect());
+        assert!(Error::SSLConnectFailed(None).is_tls());
+        assert!(!Error::Timeout.is_client_error());
+        assert!(!Error::Timeout.is_server_error());
+        assert_eq!(Error::Timeout.status(), None);
+    }
+}
@@ -1,9 +1,17 @@
+use crate::{Options, Request};
 use std::ascii;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
 
 pub mod agent;
+pub mod info;
 pub mod notify;
 pub mod parse;
 pub mod request;
+pub mod timer;
+
+pub use self::timer::delay;
 
 pub fn format_byte_string(bytes: impl AsRef<[u8]>) -> String {
     String::from_utf8(bytes
@@ -13,3 +21,167 @@ pub fn format_byte_string(bytes: impl AsRef<[u8]>) -> String {
         .collect())
         .unwrap_or(String::from("<binary>"))
 }
+
+/// Format a chunk of body data for logging, keeping at most `limit` bytes of it.
+///
+/// If any bytes are left out, a `...(truncated, N bytes in chunk)` marker giving the full length of the chunk is
+/// appended.
+///
+/// Text is escaped the same way as in [`format_byte_string`], while data that is not valid UTF-8 is logged as a hex
+/// dump instead.
+pub fn format_body(bytes: &[u8], limit: Option<usize>) -> String {
+    let truncated = &bytes[..limit.unwrap_or(bytes.len()).min(bytes.len())];
+
+    let mut formatted = match std::str::from_utf8(truncated) {
+        // A multibyte character cut off by the limit does not make the data binary.
+        Err(e) if e.error_len().is_some() => hex_dump(truncated),
+        _ => format_byte_string(truncated),
+    };
+
+    if truncated.len() < bytes.len() {
+        formatted.push_str(&format!("...(truncated, {} bytes in chunk)", bytes.len()));
+    }
+
+    formatted
+}
+
+/// Format bytes as lines of 16 hexadecimal bytes each, followed by their printable ASCII characters.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (i, line) in bytes.chunks(16).enumerate() {
+        dump.push_str(&format!("\n{:08x} ", i * 16));
+
+        for byte in line {
+            dump.push_str(&format!(" {:02x}", byte));
+        }
+        for _ in line.len()..16 {
+            dump.push_str("   ");
+        }
+
+        dump.push_str("  |");
+        dump.extend(line.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        }));
+        dump.push('|');
+    }
+
+    dump
+}
+
+/// Format a time as an HTTP date in the IMF-fixdate format of RFC 7231, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// Times before the Unix epoch are formatted as the epoch.
+pub fn format_http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let seconds = time.duration_since(SystemTime::UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    let days = seconds / 86400;
+    let seconds_of_day = seconds % 86400;
+
+    // Convert the number of days into a calendar date, using eras of 400 years starting on the 1st of March so that
+    // leap days fall at the end of each year.
+    let days_since_era = days + 719_468;
+    let era = days_since_era / 146_097;
+    let day_of_era = days_since_era % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 2 } else { shifted_month - 10 };
+    let year = era * 400 + year_of_era + if month < 2 { 1 } else { 0 };
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // The epoch was on a Thursday.
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize],
+        year,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+    )
+}
+
+/// Generate a random 64-bit number.
+///
+/// This is not cryptographically secure, but is suitable for generating unique identifiers like multipart boundaries.
+pub fn random_u64() -> u64 {
+    // Each new `RandomState` is seeded with fresh random keys, which gives us a source of randomness without pulling in
+    // an extra dependency.
+    let mut hasher = RandomState::new().build_hasher();
+
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+
+    hasher.finish()
+}
+
+/// Create a copy of a request that can be sent again, if its body can be sent more than once.
+///
+/// Extensions cannot be copied in general, so only the request options, cookie jar and cancellation are carried over.
+pub fn try_clone_request(request: &Request) -> Option<Request> {
+    let mut clone = http::Request::new(request.body().try_clone()?);
+
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+
+    if let Some(options) = request.extensions().get::<Options>() {
+        clone.extensions_mut().insert(options.clone());
+    }
+
+    if let Some(cancellation) = request.extensions().get::<self::request::Cancellation>() {
+        clone.extensions_mut().insert(cancellation.clone());
+    }
+
+    #[cfg(feature = "cookies")] {
+        if let Some(jar) = request.extensions().get::<crate::cookies::CookieJar>() {
+            clone.extensions_mut().insert(jar.clone());
+        }
+    }
+
+    Some(clone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn format_body_truncates_to_limit() {
+        assert_eq!(format_body(b"hello world", Some(5)), "hello...(truncated, 11 bytes in chunk)");
+        assert_eq!(format_body(b"hello world", Some(20)), "hello world");
+        assert_eq!(format_body(b"hello world", None), "hello world");
+    }
+
+    #[test]
+    fn format_body_hex_dumps_binary_data() {
+        assert_eq!(
+            format_body(&[0xff, 0x00, b'a'], None),
+            "\n00000000  ff 00 61                                         |..a|",
+        );
+    }
+
+    #[test]
+    fn format_body_treats_cut_off_characters_as_text() {
+        assert_eq!(format_body("h\u{e9}".as_bytes(), Some(2)), "h\\xc3...(truncated, 3 bytes in chunk)");
+    }
+
+    #[test]
+    fn format_http_dates() {
+        let date = |seconds| format_http_date(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(date(951_782_400), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(date(4_102_444_799), "Thu, 31 Dec 2099 23:59:59 GMT");
+    }
+}
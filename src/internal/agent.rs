@@ -2,6 +2,7 @@
 
 use crate::error::Error;
 use crate::internal::notify;
+use crate::metrics::ClientMetrics;
 use crate::internal::request::*;
 use crossbeam_channel::{self, Sender, Receiver};
 use log::*;
@@ -15,31 +16,60 @@ const AGENT_THREAD_NAME: &'static str = "curl agent";
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 const MAX_TIMEOUT: Duration = Duration::from_millis(1000);
 
+/// Configuration for the connection pool of an agent.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Maximum number of simultaneously open connections.
+    pub max_connections: Option<usize>,
+
+    /// Maximum number of simultaneously open connections to a single host.
+    pub max_connections_per_host: Option<usize>,
+}
+
 /// Create an agent that executes multiple curl requests simultaneously.
 ///
 /// The agent maintains a background thread that multiplexes all active requests using a single "multi" handle.
-pub fn create() -> Result<Handle, Error> {
+pub fn create(config: Config) -> Result<Handle, Error> {
     let create_start = Instant::now();
 
     let (message_tx, message_rx) = crossbeam_channel::unbounded();
     let (notify_tx, notify_rx) = notify::create()?;
 
+    let counters = Arc::new(Counters::default());
     let handle_inner = Arc::new(HandleInner {
         message_tx,
         notify_tx,
+        shutting_down: AtomicBool::default(),
         thread_terminated: AtomicBool::default(),
+        counters: counters.clone(),
     });
     let handle_weak = Arc::downgrade(&handle_inner);
 
     thread::Builder::new().name(String::from(AGENT_THREAD_NAME)).spawn(move || {
+        let mut multi = curl::multi::Multi::new();
+
+        // Requests exceeding these limits are queued by curl until a connection becomes available.
+        if let Some(max) = config.max_connections {
+            if let Err(e) = multi.set_max_total_connections(max) {
+                warn!("failed to set max connections: {}", e);
+            }
+        }
+        if let Some(max) = config.max_connections_per_host {
+            if let Err(e) = multi.set_max_host_connections(max) {
+                warn!("failed to set max connections per host: {}", e);
+            }
+        }
+
         let agent = Agent {
-            multi: curl::multi::Multi::new(),
+            multi: multi,
             multi_messages: crossbeam_channel::unbounded(),
             message_rx,
             notify_rx,
             requests: Slab::new(),
             close_requested: false,
+            shutdown: None,
             handle: handle_weak,
+            counters,
         };
 
         debug!("agent took {:?} to start up", create_start.elapsed());
@@ -68,16 +98,56 @@ struct HandleInner {
     /// Used to wake up the agent thread while it is polling.
     notify_tx: notify::NotifySender,
 
+    /// Indicates that the agent is shutting down and no longer accepts new requests.
+    shutting_down: AtomicBool,
+
     /// Indicates that the agent thread has exited.
     thread_terminated: AtomicBool,
+
+    /// Usage counters shared with the agent thread.
+    counters: Arc<Counters>,
+}
+
+/// Usage counters for an agent, updated by both handles and the agent thread.
+#[derive(Debug, Default)]
+struct Counters {
+    requests_sent: AtomicUsize,
+    active_requests: AtomicUsize,
+    connections_opened: AtomicUsize,
 }
 
 impl Handle {
     /// Begin executing a request with this agent.
     pub fn begin_execute(&self, request: CurlRequest) -> Result<(), Error> {
+        if self.inner.shutting_down.load(Ordering::SeqCst) {
+            debug!("rejecting request because the agent is shutting down");
+            return Err(Error::Canceled);
+        }
+
         request.0.get_ref().set_agent(self.clone());
 
-        self.inner.send_message(Message::BeginRequest(request))
+        // Count the request before sending it, since the agent may finish it before we get a chance to.
+        self.inner.counters.active_requests.fetch_add(1, Ordering::Relaxed);
+
+        if let Err(e) = self.inner.send_message(Message::BeginRequest(request)) {
+            self.inner.counters.active_requests.fetch_sub(1, Ordering::Relaxed);
+            return Err(e);
+        }
+
+        self.inner.counters.requests_sent.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Get a snapshot of the usage counters of this agent.
+    pub fn metrics(&self) -> ClientMetrics {
+        let counters = &self.inner.counters;
+
+        ClientMetrics {
+            requests_sent: counters.requests_sent.load(Ordering::Relaxed),
+            active_requests: counters.active_requests.load(Ordering::Relaxed),
+            connections_opened: counters.connections_opened.load(Ordering::Relaxed),
+        }
     }
 
     /// Cancel a request by its token.
@@ -85,10 +155,30 @@ impl Handle {
         self.inner.send_message(Message::Cancel(token))
     }
 
+    /// Resume sending the request body of a request by its token.
+    pub fn unpause_read(&self, token: usize) -> Result<(), Error> {
+        self.inner.send_message(Message::UnpauseRead(token))
+    }
+
     /// Unpause a request by its token.
     pub fn unpause_write(&self, token: usize) -> Result<(), Error> {
         self.inner.send_message(Message::UnpauseWrite(token))
     }
+
+    /// Stop accepting new requests, and stop the agent once all active requests have completed or the timeout has
+    /// elapsed, whichever comes first. Blocks until the agent has stopped.
+    pub fn shutdown(&self, timeout: Duration) {
+        if self.inner.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let (done_tx, done_rx) = crossbeam_channel::bounded(0);
+
+        if self.inner.send_message(Message::Shutdown(Instant::now() + timeout, done_tx)).is_ok() {
+            // The agent never sends anything, it just drops the sender once it has stopped.
+            let _ = done_rx.recv();
+        }
+    }
 }
 
 impl HandleInner {
@@ -110,6 +200,11 @@ impl HandleInner {
 
 impl Drop for HandleInner {
     fn drop(&mut self) {
+        // An agent that was shut down has already stopped.
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+
         if self.send_message(Message::Close).is_err() {
             warn!("agent thread was already terminated");
         }
@@ -121,7 +216,9 @@ impl Drop for HandleInner {
 enum Message {
     Cancel(usize),
     Close,
+    Shutdown(Instant, Sender<()>),
     BeginRequest(CurlRequest),
+    UnpauseRead(usize),
     UnpauseWrite(usize),
 }
 
@@ -147,8 +244,15 @@ struct Agent {
     /// Indicates if the thread has been requested to stop.
     close_requested: bool,
 
+    /// The deadline for active requests to complete if a graceful shutdown has been requested, along with a sender
+    /// that is dropped along with the agent to signal that it has stopped.
+    shutdown: Option<(Instant, Sender<()>)>,
+
     /// Weak reference to a handle, used to communicate back to handles.
     handle: Weak<HandleInner>,
+
+    /// Usage counters shared with handles.
+    counters: Arc<Counters>,
 }
 
 impl Agent {
@@ -166,6 +270,17 @@ impl Agent {
             // Perform any pending reads or writes and handle any state changes.
             self.dispatch()?;
 
+            if let Some((deadline, _)) = self.shutdown.as_ref() {
+                if self.requests.is_empty() {
+                    debug!("all requests completed, finishing shutdown");
+                    self.close_requested = true;
+                } else if Instant::now() >= *deadline {
+                    debug!("aborting {} requests still active at shutdown deadline", self.requests.len());
+                    self.abort_all_requests()?;
+                    self.close_requested = true;
+                }
+            }
+
             if self.close_requested {
                 break;
             }
@@ -183,8 +298,11 @@ impl Agent {
                 timeout = Duration::from_millis(1);
             }
 
-            // Truncate the timeout to the max value.
+            // Truncate the timeout to the max value, and wake up in time for any shutdown deadline.
             timeout = timeout.min(MAX_TIMEOUT);
+            if let Some((deadline, _)) = self.shutdown.as_ref() {
+                timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+            }
 
             // Block until activity is detected or the timeout passes.
             if timeout > Duration::from_secs(0) {
@@ -211,7 +329,7 @@ impl Agent {
     /// If there are no active requests right now, this function will block until a message is received.
     fn poll_messages(&mut self) -> Result<(), Error> {
         loop {
-            if !self.close_requested && self.requests.is_empty() {
+            if !self.close_requested && self.shutdown.is_none() && self.requests.is_empty() {
                 match self.message_rx.recv() {
                     Ok(message) => self.handle_message(message)?,
                     _ => {
@@ -244,6 +362,10 @@ impl Agent {
                 trace!("agent close requested");
                 self.close_requested = true;
             },
+            Message::Shutdown(deadline, done) => {
+                debug!("agent shutdown requested, waiting for {} active requests", self.requests.len());
+                self.shutdown = Some((deadline, done));
+            },
             Message::BeginRequest(request) => {
                 let mut handle = self.multi.add2(request.0)?;
                 let entry = self.requests.vacant_entry();
@@ -257,9 +379,17 @@ impl Agent {
                 if self.requests.contains(token) {
                     let request = self.requests.remove(token);
                     let request = self.multi.remove2(request)?;
+                    self.request_finished(&request);
                     drop(request);
                 }
             },
+            Message::UnpauseRead(token) => {
+                if let Some(request) = self.requests.get(token) {
+                    request.unpause_read()?;
+                } else {
+                    warn!("received unpause request for unknown request token: {}", token);
+                }
+            },
             Message::UnpauseWrite(token) => {
                 if let Some(request) = self.requests.get(token) {
                     request.unpause_write()?;
@@ -302,6 +432,7 @@ impl Agent {
         debug!("request with token {} completed", token);
         let handle = self.requests.remove(token);
         let mut handle = self.multi.remove2(handle)?;
+        self.request_finished(&handle);
         handle.get_mut().complete();
 
         Ok(())
@@ -310,10 +441,31 @@ impl Agent {
     fn fail_request(&mut self, token: usize, error: curl::Error) -> Result<(), Error> {
         let handle = self.requests.remove(token);
         let mut handle = self.multi.remove2(handle)?;
+        self.request_finished(&handle);
         handle.get_mut().fail(error);
 
         Ok(())
     }
+
+    /// Fail all active requests with a timeout error.
+    fn abort_all_requests(&mut self) -> Result<(), Error> {
+        let tokens: Vec<usize> = self.requests.iter().map(|(token, _)| token).collect();
+
+        for token in tokens {
+            self.fail_request(token, curl::Error::new(curl_sys::CURLE_OPERATION_TIMEDOUT))?;
+        }
+
+        Ok(())
+    }
+
+    /// Update the usage counters for a request that is no longer active.
+    fn request_finished(&self, handle: &curl::easy::Easy2<CurlHandler>) {
+        self.counters.active_requests.fetch_sub(1, Ordering::Relaxed);
+
+        if let Ok(count) = handle.num_connects() {
+            self.counters.connections_opened.fetch_add(count as usize, Ordering::Relaxed);
+        }
+    }
 }
 
 impl Drop for Agent {
@@ -0,0 +1,87 @@
+//! Helpers for querying transfer information from a curl easy handle.
+//!
+//! The safe wrappers provided by the curl crate require access to the `Easy2` object itself, which is not available
+//! from inside of the handler callbacks. These helpers work on the raw handle instead, which libcurl allows to be
+//! queried from inside of its own callbacks.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_double, c_long};
+use std::ptr;
+use std::time::Duration;
+
+/// A raw pointer to a curl easy handle.
+#[derive(Clone, Copy, Debug)]
+pub struct RawHandle(*mut curl_sys::CURL);
+
+// The handle is only ever dereferenced by libcurl from the agent thread while the easy handle is alive.
+unsafe impl Send for RawHandle {}
+
+impl RawHandle {
+    /// Create a handle that does not point to anything yet. All queries will return `None`.
+    pub fn null() -> Self {
+        RawHandle(ptr::null_mut())
+    }
+
+    /// Wrap a raw curl easy handle.
+    pub fn new(handle: *mut curl_sys::CURL) -> Self {
+        RawHandle(handle)
+    }
+
+    /// Get a string value of the given info.
+    pub fn get_str(&self, info: curl_sys::CURLINFO) -> Option<String> {
+        if self.0.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let mut value: *const c_char = ptr::null();
+
+            if curl_sys::curl_easy_getinfo(self.0, info, &mut value) != curl_sys::CURLE_OK || value.is_null() {
+                return None;
+            }
+
+            CStr::from_ptr(value).to_str().ok().map(ToOwned::to_owned)
+        }
+    }
+
+    /// Get an integer value of the given info.
+    pub fn get_long(&self, info: curl_sys::CURLINFO) -> Option<c_long> {
+        if self.0.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let mut value: c_long = 0;
+
+            if curl_sys::curl_easy_getinfo(self.0, info, &mut value) != curl_sys::CURLE_OK {
+                return None;
+            }
+
+            Some(value)
+        }
+    }
+
+    /// Get a floating point value of the given info.
+    pub fn get_double(&self, info: curl_sys::CURLINFO) -> Option<c_double> {
+        if self.0.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let mut value: c_double = 0.0;
+
+            if curl_sys::curl_easy_getinfo(self.0, info, &mut value) != curl_sys::CURLE_OK {
+                return None;
+            }
+
+            Some(value)
+        }
+    }
+
+    /// Get a time value of the given info, which curl reports in fractional seconds.
+    pub fn get_duration(&self, info: curl_sys::CURLINFO) -> Option<Duration> {
+        self.get_double(info)
+            .filter(|seconds| *seconds >= 0.0)
+            .map(|seconds| Duration::from_nanos((seconds * 1e9) as u64))
+    }
+}
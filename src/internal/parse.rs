@@ -1,23 +1,29 @@
-use http::{StatusCode, Version};
+use http::{StatusCode, Uri, Version};
 use http::header::*;
 use lazy_static::lazy_static;
 use regex::bytes::Regex;
 
 lazy_static! {
-    static ref STATUS_LINE_REGEX: Regex = r#"^HTTP/(\d(?:\.\d)?) (\d{3})"#.parse().unwrap();
+    static ref STATUS_LINE_REGEX: Regex = r#"^HTTP/(\d(?:\.\d)?) (\d{3})(?: ([^\r\n]*))?"#.parse().unwrap();
     static ref HEADER_LINE_REGEX: Regex = r#"^([^:]+): *([^\r]*)\r\n$"#.parse().unwrap();
 }
 
-pub fn parse_status_line(line: &[u8]) -> Option<(Version, StatusCode)> {
+/// Parse a response status line into its version, status code, and reason phrase, if any.
+pub fn parse_status_line(line: &[u8]) -> Option<(Version, StatusCode, Option<String>)> {
     STATUS_LINE_REGEX.captures(line).and_then(|captures| Some((
         match &captures[1] {
-            b"HTTP/2" => Version::HTTP_2,
-            b"HTTP/1.1" => Version::HTTP_11,
-            b"HTTP/1.0" => Version::HTTP_10,
-            b"HTTP/0.9" => Version::HTTP_09,
+            b"2" | b"2.0" => Version::HTTP_2,
+            b"1.1" => Version::HTTP_11,
+            b"1.0" => Version::HTTP_10,
+            b"0.9" => Version::HTTP_09,
             _ => Version::default(),
         },
         StatusCode::from_bytes(&captures[2]).ok()?,
+        captures.get(3)
+            .and_then(|reason| std::str::from_utf8(reason.as_bytes()).ok())
+            .map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .map(ToOwned::to_owned),
     )))
 }
 
@@ -27,3 +33,73 @@ pub fn parse_header(line: &[u8]) -> Option<(HeaderName, HeaderValue)> {
         HeaderValue::from_bytes(&captures[2]).ok()?,
     )))
 }
+
+/// Resolve the value of a `Location` header against the URI of the request that it was received for.
+pub fn resolve_uri(base: &Uri, location: &str) -> Option<Uri> {
+    // Absolute URI.
+    if let Ok(uri) = location.parse::<Uri>() {
+        if uri.scheme_part().is_some() {
+            return Some(uri);
+        }
+    }
+
+    let scheme = base.scheme_str()?;
+    let authority = base.authority_part()?;
+
+    let uri = if location.starts_with("//") {
+        format!("{}:{}", scheme, location)
+    } else if location.starts_with('/') {
+        format!("{}://{}{}", scheme, authority, location)
+    } else if location.starts_with('?') {
+        format!("{}://{}{}{}", scheme, authority, base.path(), location)
+    } else {
+        // Relative path, which replaces the last segment of the base path.
+        let path = base.path();
+        let directory = &path[..path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{}://{}{}{}", scheme, authority, directory, location)
+    };
+
+    uri.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_line_reason_phrase() {
+        let (_, status, reason) = parse_status_line(b"HTTP/1.1 499 Client Went Away\r\n").unwrap();
+        assert_eq!(status, 499);
+        assert_eq!(reason.as_ref().map(String::as_str), Some("Client Went Away"));
+
+        let (_, status, reason) = parse_status_line(b"HTTP/2 200\r\n").unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reason, None);
+    }
+
+    #[test]
+    fn parse_status_line_version() {
+        for case in &[
+            (&b"HTTP/1.0 200 OK\r\n"[..], Version::HTTP_10),
+            (&b"HTTP/1.1 200 OK\r\n"[..], Version::HTTP_11),
+            (&b"HTTP/2 200\r\n"[..], Version::HTTP_2),
+        ] {
+            assert_eq!(parse_status_line(case.0).unwrap().0, case.1);
+        }
+    }
+
+    #[test]
+    fn resolve_location() {
+        let base: Uri = "https://example.org/foo/bar?baz".parse().unwrap();
+
+        for case in &[
+            ("http://other.org/", "http://other.org/"),
+            ("//other.org/a", "https://other.org/a"),
+            ("/a/b", "https://example.org/a/b"),
+            ("a/b", "https://example.org/foo/a/b"),
+            ("?qux", "https://example.org/foo/bar?qux"),
+        ] {
+            assert_eq!(resolve_uri(&base, case.0).unwrap().to_string(), case.1);
+        }
+    }
+}
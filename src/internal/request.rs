@@ -2,73 +2,192 @@ use bytes::Bytes;
 use crate::body::Body;
 use crate::error::Error;
 use crate::internal::agent;
-use crate::internal::format_byte_string;
+use crate::internal::{format_body, format_byte_string, format_http_date};
+use crate::internal::info::RawHandle;
 use crate::internal::parse;
+use crate::metrics::{Metrics, Timings};
 use crate::options::*;
+use crate::response::{ConnectionInfo, EffectiveUri, ReasonPhrase, RedirectCount};
 use curl::easy::InfoType;
-use futures::io::{AsyncRead, AsyncReadExt};
+use futures::io::AsyncRead;
 use futures::future::Future;
 use futures::channel::oneshot;
-use futures::executor;
-use futures::task::{Poll, Context, AtomicWaker};
-use futures::future::FutureExt;
+use futures::task::{Poll, Context, ArcWake, AtomicWaker};
 use http::{Request, Response};
 use lazycell::AtomicLazyCell;
 use log::*;
+use std::ffi::{CStr, CString};
 use std::io::{self, Read};
-use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::{c_char, c_int, c_long, c_void};
+use std::panic;
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::sync::atomic::*;
+use std::time::Duration;
 
 const STATUS_READY: usize = 0;
 const STATUS_CLOSED: usize = 1;
 
+/// The info for getting the HTTP version used for a request, which curl-sys does not define.
+const CURLINFO_HTTP_VERSION: curl_sys::CURLINFO = curl_sys::CURLINFO_LONG + 46;
+
+/// The option for enabling TCP Fast Open, which curl-sys does not define.
+const CURLOPT_TCP_FASTOPEN: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 244;
+
+/// The option and value for sending proxy headers separately from request headers, which curl-sys does not define.
+const CURLOPT_HEADEROPT: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 229;
+const CURLHEADER_SEPARATE: c_long = 1;
+
+/// The option for setting the delay before falling back to IPv4, which curl-sys does not define.
+const CURLOPT_HAPPY_EYEBALLS_TIMEOUT_MS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_LONG + 271;
+
+/// The option for setting TLS 1.3 cipher suites, which curl-sys does not define.
+const CURLOPT_TLS13_CIPHERS: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_OBJECTPOINT + 276;
+
+/// The options for setting a trailing header callback, which curl-sys does not define.
+const CURLOPT_TRAILERFUNCTION: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_FUNCTIONPOINT + 283;
+const CURLOPT_TRAILERDATA: curl_sys::CURLoption = curl_sys::CURLOPTTYPE_OBJECTPOINT + 284;
+const CURL_TRAILERFUNC_OK: c_int = 0;
+const CURL_TRAILERFUNC_ABORT: c_int = 1;
+
+/// Return values of a socket option callback, which curl-sys does not define.
+const CURL_SOCKOPT_OK: c_int = 0;
+const CURL_SOCKOPT_ERROR: c_int = 1;
+
+/// The error returned when a feature is not built into libcurl, which curl-sys does not define.
+const CURLE_NOT_BUILT_IN: curl_sys::CURLcode = 4;
+
+/// The range of receive buffer sizes accepted by curl.
+const BUFFER_SIZE_RANGE: (usize, usize) = (1024, 10 * 1024 * 1024);
+
+/// The range of upload buffer sizes accepted by curl.
+const UPLOAD_BUFFER_SIZE_RANGE: (usize, usize) = (16 * 1024, 2 * 1024 * 1024);
+
 /// Create a new curl request.
 pub fn create<B: Into<Body>>(request: Request<B>, options: &Options) -> Result<(CurlRequest, impl Future<Output=Result<Response<Body>, Error>>), Error> {
     // Set up the plumbing...
     let (future_tx, future_rx) = oneshot::channel();
-    let (request_parts, request_body) = request.into_parts();
+    let (mut request_parts, request_body) = request.into_parts();
+    let mut request_body = request_body.into();
+    let cancellation = request_parts.extensions.remove::<Cancellation>();
+
+    // HTTP/1.0 has no chunked transfer encoding, so a body of unknown length must be buffered to find its length.
+    if options.preferred_http_version == Some(http::Version::HTTP_10) {
+        request_body = buffer_unsized_body(request_body)?;
+    }
+
+    // Bearer tokens are sent as a plain header, unless the request already has one.
+    if let Some(Authentication::Bearer(token)) = &options.authentication {
+        let value = http::HeaderValue::from_str(&format!("Bearer {}", token)).map_err(http::Error::from)?;
+        request_parts.headers
+            .entry(http::header::AUTHORIZATION)
+            .unwrap()
+            .or_insert(value);
+    }
 
     let mut easy = curl::easy::Easy2::new(CurlHandler {
         state: Arc::new(RequestState::new(options.clone())),
+        handle: RawHandle::null(),
+        metrics: if options.metrics {
+            Some(Metrics::default())
+        } else {
+            None
+        },
         future: Some(future_tx),
-        request_body: request_body.into(),
+        request_method: request_parts.method.clone(),
+        request_headers: request_parts.headers.clone(),
+        request_body: request_body,
+        redirect_rejected: false,
+        response_body_received: 0,
         version: None,
         status_code: None,
+        reason_phrase: None,
         headers: http::HeaderMap::default(),
+        wire_logged_in: 0,
+        wire_logged_out: 0,
     });
 
-    easy.verbose(log_enabled!(Level::Trace))?;
+    let handle = RawHandle::new(easy.raw());
+    easy.get_mut().handle = handle;
+
+    if let Some(cancellation) = cancellation {
+        if !cancellation.register_request(&easy.get_ref().state) {
+            return Err(Error::Canceled);
+        }
+    }
+
+    easy.verbose(options.verbose || log_enabled!(Level::Trace))?;
     easy.signal(false)?;
-    easy.buffer_size(options.buffer_size)?;
+    easy.buffer_size(clamp_buffer_size("buffer_size", options.buffer_size, BUFFER_SIZE_RANGE))?;
+    if let Some(size) = options.upload_buffer_size {
+        easy.upload_buffer_size(clamp_buffer_size("upload_buffer_size", size, UPLOAD_BUFFER_SIZE_RANGE))?;
+    }
+
+    match options.byte_range {
+        Some((start, Some(end))) => easy.range(&format!("{}-{}", start, end))?,
+        Some((start, None)) => easy.range(&format!("{}-", start))?,
+        None => {},
+    }
+    if let Some(max) = options.max_response_size {
+        easy.max_filesize(max)?;
+    }
 
     if let Some(timeout) = options.timeout {
         easy.timeout(timeout)?;
     }
 
-    easy.connect_timeout(options.connect_timeout)?;
+    if let Some((limit, duration)) = options.low_speed_timeout {
+        easy.low_speed_limit(limit)?;
+        easy.low_speed_time(duration)?;
+    }
+
+    // Curl treats a connect timeout of zero as its default.
+    easy.connect_timeout(options.connect_timeout.unwrap_or_default())?;
 
     easy.tcp_nodelay(options.tcp_nodelay)?;
     if let Some(interval) = options.tcp_keepalive {
         easy.tcp_keepalive(true)?;
+        easy.tcp_keepidle(interval)?;
         easy.tcp_keepintvl(interval)?;
     } else {
         easy.tcp_keepalive(false)?;
     }
+    if options.tcp_fastopen {
+        enable_tcp_fastopen(&mut easy);
+    }
+
+    easy.ip_resolve(options.ip_version.into())?;
+    if let Some(timeout) = options.happy_eyeballs_timeout {
+        set_happy_eyeballs_timeout(&mut easy, timeout);
+    }
 
-    match options.redirect_policy {
+    match &options.redirect_policy {
         RedirectPolicy::None => {
             easy.follow_location(false)?;
         }
-        RedirectPolicy::Follow => {
+        RedirectPolicy::Follow | RedirectPolicy::Custom(_) => {
             easy.follow_location(true)?;
         }
         RedirectPolicy::Limit(max) => {
             easy.follow_location(true)?;
-            easy.max_redirections(max)?;
+            easy.max_redirections(*max)?;
         }
     }
+    easy.autoreferer(options.auto_referer)?;
+    easy.post_redirections(&options.post_redirect.into())?;
+
+    // Progress callbacks are disabled by default in curl.
+    easy.progress(options.progress.is_some())?;
+
+    if options.trailers.is_some() {
+        set_trailer_function(&mut easy)?;
+    }
+
+    if options.socket_callback.is_some() {
+        set_sockopt_function(&mut easy)?;
+    }
 
     if let Some(limit) = options.max_upload_speed {
         easy.max_send_speed(limit)?;
@@ -78,7 +197,8 @@ pub fn create<B: Into<Body>>(request: Request<B>, options: &Options) -> Result<(
         easy.max_recv_speed(limit)?;
     }
 
-    // Set a preferred HTTP version to negotiate.
+    // Set a preferred HTTP version to negotiate. Curl never uses a newer version than the one requested, and only falls
+    // back to an older version when HTTP/2 is requested.
     easy.http_version(match options.preferred_http_version {
         Some(http::Version::HTTP_10) => curl::easy::HttpVersion::V10,
         Some(http::Version::HTTP_11) => curl::easy::HttpVersion::V11,
@@ -86,8 +206,82 @@ pub fn create<B: Into<Body>>(request: Request<B>, options: &Options) -> Result<(
         _ => curl::easy::HttpVersion::Any,
     })?;
 
+    // Skip the HTTP/1.1 upgrade dance if the server is known to speak cleartext HTTP/2.
+    if options.http2_prior_knowledge && request_parts.uri.scheme_part() == Some(&http::uri::Scheme::HTTP) {
+        easy.http_version(curl::easy::HttpVersion::V2PriorKnowledge)?;
+    }
+
+    match &options.authentication {
+        Some(Authentication::Basic {username, password}) => {
+            easy.username(username)?;
+            easy.password(password)?;
+            easy.http_auth(curl::easy::Auth::new().basic(true))?;
+        },
+        Some(Authentication::Ntlm {username, password}) => {
+            easy.username(username)?;
+            easy.password(password)?;
+            easy.http_auth(curl::easy::Auth::new().ntlm(true))?;
+        },
+        _ => {},
+    }
+
+    easy.netrc(options.netrc.into())?;
+    if let Some(path) = options.netrc_file.as_ref() {
+        set_netrc_file(&mut easy, path)?;
+    }
+
     if let Some(ref proxy) = options.proxy {
         easy.proxy(&format!("{}", proxy))?;
+        easy.http_proxy_tunnel(options.proxy_tunnel)?;
+
+        if !options.proxy_blacklist.is_empty() {
+            // Curl does not understand wildcards, but matches subdomains of a bare domain anyway.
+            let noproxy = options.proxy_blacklist.iter()
+                .map(|host| host.trim_start_matches("*."))
+                .collect::<Vec<_>>()
+                .join(",");
+            easy.noproxy(&noproxy)?;
+        }
+
+        match &options.proxy_authentication {
+            Some(Authentication::Basic {username, password}) => {
+                easy.proxy_username(username)?;
+                easy.proxy_password(password)?;
+                easy.proxy_auth(curl::easy::Auth::new().basic(true))?;
+            },
+            Some(Authentication::Ntlm {username, password}) => {
+                easy.proxy_username(username)?;
+                easy.proxy_password(password)?;
+                easy.proxy_auth(curl::easy::Auth::new().ntlm(true))?;
+            },
+            // Bearer tokens are sent as a header only to the proxy, so that they do not leak to the server through a
+            // tunnel. A header already in the request takes precedence.
+            Some(Authentication::Bearer(token)) => {
+                if !request_parts.headers.contains_key(http::header::PROXY_AUTHORIZATION) {
+                    // Make sure the token cannot smuggle in other headers.
+                    http::HeaderValue::from_str(token).map_err(http::Error::from)?;
+                    let mut headers = curl::easy::List::new();
+                    headers.append(&format!("Proxy-Authorization: Bearer {}", token))?;
+                    easy.proxy_headers(headers)?;
+                    set_separate_proxy_headers(&mut easy)?;
+                }
+            },
+            None => {},
+        }
+    }
+
+    if let Some(path) = &options.unix_socket {
+        let path = path.to_str()
+            .ok_or_else(|| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "unix socket path is not valid UTF-8")))?;
+        easy.unix_socket(path)?;
+    }
+
+    if let Some(interface) = &options.interface {
+        easy.interface(interface)?;
+    }
+    if let Some((start, count)) = options.local_ports {
+        easy.set_local_port(start)?;
+        easy.local_port_range(count.max(1))?;
     }
 
     if let Some(addrs) = &options.dns_servers {
@@ -95,52 +289,487 @@ pub fn create<B: Into<Body>>(request: Request<B>, options: &Options) -> Result<(
             .map(ToString::to_string)
             .collect::<Vec<_>>()
             .join(",");
-        if let Err(e) = easy.dns_servers(&dns_string) {
-            warn!("DNS servers could not be configured: {}", e);
+        easy.dns_servers(&dns_string).map_err(|e| if e.code() == CURLE_NOT_BUILT_IN || e.is_unknown_option() {
+            Error::Curl(String::from("custom DNS servers are not supported, libcurl must be built with c-ares"))
+        } else {
+            e.into()
+        })?;
+    }
+
+    if !options.dns_overrides.is_empty() {
+        // Curl expects all addresses for the same host and port in a single entry.
+        let mut entries: Vec<(&str, u16, Vec<String>)> = Vec::new();
+        for (host, port, addr) in &options.dns_overrides {
+            let addr = match addr {
+                IpAddr::V4(addr) => addr.to_string(),
+                IpAddr::V6(addr) => format!("[{}]", addr),
+            };
+
+            match entries.iter_mut().find(|entry| entry.0 == host.as_str() && entry.1 == *port) {
+                Some(entry) => entry.2.push(addr),
+                None => entries.push((host.as_str(), *port, vec![addr])),
+            }
+        }
+
+        let mut resolve = curl::easy::List::new();
+        for (host, port, addrs) in entries {
+            resolve.append(&format!("{}:{}:{}", host, port, addrs.join(",")))?;
         }
+        easy.resolve(resolve)?;
+    }
+    if !options.connect_to.is_empty() {
+        let mut connect_to = curl::easy::List::new();
+        for rule in &options.connect_to {
+            // IPv6 addresses must be enclosed in brackets.
+            let connect_host = if rule.connect_host.contains(':') && !rule.connect_host.starts_with('[') {
+                format!("[{}]", rule.connect_host)
+            } else {
+                rule.connect_host.clone()
+            };
+            connect_to.append(&format!("{}:{}:{}:{}", rule.host, rule.port, connect_host, rule.connect_port))?;
+        }
+        easy.connect_to(connect_to)?;
     }
 
     // Configure SSL options.
+    if let Some((min, max)) = options.tls_version_range {
+        easy.ssl_min_max_version(min.into(), max.map(Into::into).unwrap_or(curl::easy::SslVersion::Default))?;
+    }
     if let Some(ciphers) = &options.ssl_ciphers {
         easy.ssl_cipher_list(&ciphers.join(":"))?;
     }
+    if let Some(ciphers) = &options.tls13_ciphers {
+        set_tls13_ciphers(&mut easy, &ciphers.join(":"))?;
+    }
+    if let Some(pins) = &options.pinned_public_key {
+        easy.pinned_public_key(pins)?;
+    }
     if let Some(cert) = &options.ssl_client_certificate {
+        check_client_certificate(cert)?;
         easy.ssl_client_certificate(cert)?;
     }
+    easy.ssl_verify_peer(options.ssl_verify)?;
+    easy.ssl_verify_host(options.ssl_verify)?;
+    easy.ssl_sessionid_cache(options.ssl_session_reuse)?;
+    if let Some(path) = &options.ca_certificate {
+        if !path.is_file() {
+            return Err(Error::BadServerCertificate(Some(format!("CA certificate file not found: {}", path.display()))));
+        }
+        easy.cainfo(path)?;
+
+        // Curl may have a default CA directory compiled in, which would still be trusted alongside the bundle unless
+        // a directory is given explicitly.
+        if options.ca_path.is_none() {
+            unset_option(&mut easy, curl_sys::CURLOPT_CAPATH)?;
+        }
+    }
+    if let Some(path) = &options.ca_path {
+        if !path.is_dir() {
+            return Err(Error::BadServerCertificate(Some(format!("CA certificate directory not found: {}", path.display()))));
+        }
+        easy.capath(path)?;
+    }
 
-    // Set the request data according to the request given.
-    easy.custom_request(request_parts.method.as_str())?;
+    // Set the request data according to the request given. The method is always sent as given, including extension
+    // methods, since curl would otherwise pick a method based on whether there is a body to upload.
+    let is_post = request_parts.method == http::Method::POST;
+    if request_parts.method == http::Method::HEAD {
+        // Curl must be told not to wait for a body, which a HEAD response never has.
+        easy.nobody(true)?;
+    } else if is_post {
+        // Curl only applies the post redirect policy to requests it knows to be POST requests, and keeps sending a
+        // custom method even when switching to GET.
+        easy.post(true)?;
+    } else {
+        easy.custom_request(request_parts.method.as_str())?;
+    }
     easy.url(&request_parts.uri.to_string())?;
+    easy.path_as_is(options.path_as_is)?;
 
     let mut headers = curl::easy::List::new();
     for (name, value) in request_parts.headers.iter() {
+        // The expect header is controlled by the expect continue option.
+        if name == http::header::EXPECT {
+            continue;
+        }
+
+        // Cookie headers are merged with the cookies option below.
+        if name == http::header::COOKIE && options.cookies.is_some() {
+            continue;
+        }
+
         let header = format!("{}: {}", name.as_str(), value.to_str().unwrap());
         headers.append(&header)?;
     }
+
+    // Curl adds a form content type to POST requests by default, which may not describe the body at all.
+    if is_post && !request_parts.headers.contains_key(http::header::CONTENT_TYPE) {
+        headers.append("Content-Type:")?;
+    }
+
+    // Headers in the request take precedence over the conditional request options.
+    if let Some(time) = options.if_modified_since {
+        if !request_parts.headers.contains_key(http::header::IF_MODIFIED_SINCE) {
+            headers.append(&format!("If-Modified-Since: {}", format_http_date(time)))?;
+        }
+    }
+    if let Some(etag) = &options.if_none_match {
+        if !request_parts.headers.contains_key(http::header::IF_NONE_MATCH) {
+            // Make sure the value cannot smuggle in other headers.
+            http::header::HeaderValue::from_str(etag).map_err(http::Error::from)?;
+            headers.append(&format!("If-None-Match: {}", etag))?;
+        }
+    }
+
+    match options.expect_continue {
+        Some(timeout) => easy.expect_100_timeout(timeout)?,
+        // An empty header value tells curl to not send the header at all.
+        None => headers.append("Expect:")?,
+    }
+
     easy.http_headers(headers)?;
 
-    // Enable automatic response decompression.
-    easy.accept_encoding("")?;
+    // Curl ignores the cookie option if the request has a cookie header of its own, so any cookies already in the
+    // request are sent as part of the option instead.
+    if let Some(cookies) = &options.cookies {
+        let mut values = request_parts.headers.get_all(http::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>();
+        values.push(cookies);
 
-    // If the request body is non-empty, tell curl that we are going to upload something.
-    if !easy.get_ref().request_body.is_empty() {
+        easy.cookie(&values.join("; "))?;
+    }
+
+    // Enable automatic response decompression, by default of all encodings supported by curl.
+    if let Some(encoding) = options.decompression_encoding() {
+        easy.accept_encoding(encoding)?;
+    }
+
+    if is_post {
+        // POST bodies are always read using the read callback. If we know the size of the request body up front, tell
+        // curl about it, otherwise it is sent using chunked encoding.
+        if let Some(len) = easy.get_ref().request_body.len() {
+            easy.post_field_size(len)?;
+        }
+    } else if !easy.get_ref().request_body.is_empty() {
+        // If the request body is non-empty, tell curl that we are going to upload something.
         easy.upload(true)?;
 
         if let Some(len) = easy.get_ref().request_body.len() {
             // If we know the size of the request body up front, tell curl about it.
-            easy.in_filesize(len as u64)?;
+            easy.in_filesize(len)?;
+        }
+    }
+
+    // Responses to HEAD requests never have a body, even if they include a content length.
+    let is_head = request_parts.method == http::Method::HEAD;
+
+    let future = async move {
+        let response = match future_rx.await {
+            Ok(result) => result?,
+            Err(_) => {
+                error!("request canceled by agent; this should never happen!");
+                return Err(Error::Canceled);
+            },
+        };
+
+        let len = response.headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .filter(|_| !is_head);
+
+        Ok(response.map(|stream| match len {
+            Some(len) => Body::from_async_reader_sized(stream, len),
+            None => Body::from_async_reader(stream),
+        }))
+    };
+
+    Ok((CurlRequest(easy), future))
+}
+
+/// Enable TCP Fast Open for a request, if the platform supports it.
+///
+/// This is best-effort, since support depends on the operating system as well as on how libcurl was built.
+fn enable_tcp_fastopen(easy: &mut curl::easy::Easy2<CurlHandler>) {
+    // Only mention missing support once, instead of for every request.
+    static LOGGED_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+    let result = unsafe {
+        curl_sys::curl_easy_setopt(easy.raw(), CURLOPT_TCP_FASTOPEN, 1 as c_long)
+    };
+
+    if result != curl_sys::CURLE_OK && !LOGGED_UNSUPPORTED.swap(true, Ordering::Relaxed) {
+        debug!("TCP Fast Open is not supported on this platform, ignoring");
+    }
+}
+
+/// Send headers set with `CURLOPT_PROXYHEADER` only to the proxy, and the request headers only to the server.
+fn set_separate_proxy_headers(easy: &mut curl::easy::Easy2<CurlHandler>) -> Result<(), Error> {
+    let result = unsafe {
+        curl_sys::curl_easy_setopt(easy.raw(), CURLOPT_HEADEROPT, CURLHEADER_SEPARATE)
+    };
+
+    if result == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(result).into())
+    }
+}
+
+/// Set how long to wait for an IPv6 connection before trying IPv4 in parallel, if curl supports it.
+fn set_happy_eyeballs_timeout(easy: &mut curl::easy::Easy2<CurlHandler>, timeout: Duration) {
+    let millis = timeout.as_millis().min(c_long::max_value() as u128) as c_long;
+
+    let result = unsafe {
+        curl_sys::curl_easy_setopt(easy.raw(), CURLOPT_HAPPY_EYEBALLS_TIMEOUT_MS, millis)
+    };
+
+    if result != curl_sys::CURLE_OK {
+        debug!("happy eyeballs timeout is not supported by this version of curl, ignoring");
+    }
+}
+
+/// Set the cipher suites to use for TLS 1.3 connections.
+fn set_tls13_ciphers(easy: &mut curl::easy::Easy2<CurlHandler>, ciphers: &str) -> Result<(), Error> {
+    let ciphers = CString::new(ciphers)
+        .map_err(|_| Error::SSLEngineError(Some(String::from("cipher list contains a nul byte"))))?;
+
+    setopt_str(easy, CURLOPT_TLS13_CIPHERS, &ciphers)
+}
+
+/// Set the file to read `.netrc` credentials from.
+fn set_netrc_file(easy: &mut curl::easy::Easy2<CurlHandler>, path: &Path) -> Result<(), Error> {
+    let path = path.to_str()
+        .and_then(|path| CString::new(path).ok())
+        .ok_or_else(|| Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "invalid .netrc file path")))?;
+
+    setopt_str(easy, curl_sys::CURLOPT_NETRC_FILE, &path)
+}
+
+/// Reset a string option to null, which curl does not allow through its safe API.
+fn unset_option(easy: &mut curl::easy::Easy2<CurlHandler>, option: curl_sys::CURLoption) -> Result<(), Error> {
+    let result = unsafe {
+        curl_sys::curl_easy_setopt(easy.raw(), option, std::ptr::null::<c_char>())
+    };
+
+    if result == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(result).into())
+    }
+}
+
+/// Set a string option that the curl crate does not provide a setter for.
+fn setopt_str(easy: &mut curl::easy::Easy2<CurlHandler>, option: curl_sys::CURLoption, value: &CStr) -> Result<(), Error> {
+    // Curl makes its own copy of the string.
+    let result = unsafe {
+        curl_sys::curl_easy_setopt(easy.raw(), option, value.as_ptr())
+    };
+
+    if result == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(result).into())
+    }
+}
+
+/// Install the callback that asks the trailer handler in the request options for trailing headers.
+fn set_trailer_function(easy: &mut curl::easy::Easy2<CurlHandler>) -> Result<(), Error> {
+    type TrailerCallback = extern "C" fn(*mut *mut curl_sys::curl_slist, *mut c_void) -> c_int;
+
+    // The request state is kept alive by the handler for as long as the easy handle exists.
+    let state = &*easy.get_ref().state as *const RequestState as *mut c_void;
+
+    let result = unsafe {
+        match curl_sys::curl_easy_setopt(easy.raw(), CURLOPT_TRAILERFUNCTION, trailer_callback as TrailerCallback) {
+            curl_sys::CURLE_OK => curl_sys::curl_easy_setopt(easy.raw(), CURLOPT_TRAILERDATA, state),
+            error => error,
         }
+    };
+
+    if result == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(result).into())
+    }
+}
+
+/// Gets called by curl once the request body has been sent, to fill in a list of trailing headers.
+extern "C" fn trailer_callback(list: *mut *mut curl_sys::curl_slist, data: *mut c_void) -> c_int {
+    let state = unsafe { &*(data as *const RequestState) };
+
+    let trailers = match state.options.trailers.as_ref() {
+        Some(handler) => match panic::catch_unwind(panic::AssertUnwindSafe(|| handler.call())) {
+            Ok(trailers) => trailers,
+            Err(_) => {
+                error!("trailer handler panicked, aborting request");
+                return CURL_TRAILERFUNC_ABORT;
+            },
+        },
+        None => return CURL_TRAILERFUNC_OK,
+    };
+
+    for (name, value) in trailers.iter() {
+        let mut line = Vec::with_capacity(name.as_str().len() + value.len() + 2);
+        line.extend_from_slice(name.as_str().as_bytes());
+        line.extend_from_slice(b": ");
+        line.extend_from_slice(value.as_bytes());
+
+        // Header values cannot contain nul bytes, so this never fails.
+        let line = match CString::new(line) {
+            Ok(line) => line,
+            Err(_) => return CURL_TRAILERFUNC_ABORT,
+        };
+
+        // Curl copies the string, and takes ownership of the list once we return.
+        let appended = unsafe { curl_sys::curl_slist_append(*list, line.as_ptr()) };
+        if appended.is_null() {
+            return CURL_TRAILERFUNC_ABORT;
+        }
+
+        unsafe {
+            *list = appended;
+        }
+    }
+
+    CURL_TRAILERFUNC_OK
+}
+
+/// Install the callback that hands newly created sockets to the socket handler in the request options.
+fn set_sockopt_function(easy: &mut curl::easy::Easy2<CurlHandler>) -> Result<(), Error> {
+    type SockoptCallback = extern "C" fn(*mut c_void, curl_sys::curl_socket_t, curl_sys::curlsocktype) -> c_int;
+
+    // The request state is kept alive by the handler for as long as the easy handle exists.
+    let state = &*easy.get_ref().state as *const RequestState as *mut c_void;
+
+    let result = unsafe {
+        match curl_sys::curl_easy_setopt(easy.raw(), curl_sys::CURLOPT_SOCKOPTFUNCTION, sockopt_callback as SockoptCallback) {
+            curl_sys::CURLE_OK => curl_sys::curl_easy_setopt(easy.raw(), curl_sys::CURLOPT_SOCKOPTDATA, state),
+            error => error,
+        }
+    };
+
+    if result == curl_sys::CURLE_OK {
+        Ok(())
+    } else {
+        Err(curl::Error::new(result).into())
     }
+}
+
+/// Gets called by curl after creating a socket, before connecting it.
+extern "C" fn sockopt_callback(data: *mut c_void, socket: curl_sys::curl_socket_t, _: curl_sys::curlsocktype) -> c_int {
+    let state = unsafe { &*(data as *const RequestState) };
+
+    let handler = match state.options.socket_callback.as_ref() {
+        Some(handler) => handler,
+        None => return CURL_SOCKOPT_OK,
+    };
 
-    let future_rx = future_rx.then(|result| match result {
-        Ok(result) => result,
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| handler.call(socket as RawSocket))) {
+        Ok(Ok(())) => CURL_SOCKOPT_OK,
+        Ok(Err(e)) => {
+            debug!("socket handler failed, aborting connection: {}", e);
+            CURL_SOCKOPT_ERROR
+        },
         Err(_) => {
-            error!("request canceled by agent; this should never happen!");
-            Err(Error::Canceled)
+            error!("socket handler panicked, aborting connection");
+            CURL_SOCKOPT_ERROR
         },
-    }).map(|response| response.map(Body::from_reader));
+    }
+}
 
-    Ok((CurlRequest(easy), future_rx))
+/// Clamp a buffer size to the range accepted by curl, warning if the configured size is out of bounds.
+fn clamp_buffer_size(option: &str, size: usize, (min, max): (usize, usize)) -> usize {
+    let clamped = size.max(min).min(max);
+
+    if clamped != size {
+        warn!("{} of {} bytes is out of the supported range, using {} bytes instead", option, size, clamped);
+    }
+
+    clamped
+}
+
+/// Read a body of unknown length into memory, so that it can be sent with a `Content-Length` header.
+fn buffer_unsized_body(mut body: Body) -> Result<Body, Error> {
+    if body.len().is_some() {
+        return Ok(body);
+    }
+
+    debug!("buffering request body of unknown length, since chunked encoding is not available in HTTP/1.0");
+
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes)?;
+
+    Ok(Body::from(bytes))
+}
+
+impl From<NetrcPolicy> for curl::easy::NetRc {
+    fn from(policy: NetrcPolicy) -> Self {
+        match policy {
+            NetrcPolicy::Ignore => curl::easy::NetRc::Ignored,
+            NetrcPolicy::Optional => curl::easy::NetRc::Optional,
+            NetrcPolicy::Required => curl::easy::NetRc::Required,
+        }
+    }
+}
+
+impl From<PostRedirectPolicy> for curl::easy::PostRedirections {
+    fn from(policy: PostRedirectPolicy) -> Self {
+        let mut redirections = curl::easy::PostRedirections::new();
+        redirections
+            .redirect_301(policy.keep_on_301)
+            .redirect_302(policy.keep_on_302)
+            .redirect_303(policy.keep_on_303);
+        redirections
+    }
+}
+
+impl From<IpVersion> for curl::easy::IpResolve {
+    fn from(version: IpVersion) -> Self {
+        match version {
+            IpVersion::V4 => curl::easy::IpResolve::V4,
+            IpVersion::V6 => curl::easy::IpResolve::V6,
+            IpVersion::Any => curl::easy::IpResolve::Any,
+        }
+    }
+}
+
+impl From<TlsVersion> for curl::easy::SslVersion {
+    fn from(version: TlsVersion) -> Self {
+        match version {
+            TlsVersion::Tls10 => curl::easy::SslVersion::Tlsv10,
+            TlsVersion::Tls11 => curl::easy::SslVersion::Tlsv11,
+            TlsVersion::Tls12 => curl::easy::SslVersion::Tlsv12,
+            TlsVersion::Tls13 => curl::easy::SslVersion::Tlsv13,
+        }
+    }
+}
+
+/// Ensure that the files making up a client certificate exist before handing them off to curl, which would otherwise
+/// only report a generic certificate problem once the connection is attempted.
+fn check_client_certificate(cert: &ClientCertificate) -> Result<(), Error> {
+    let (cert_path, key_path) = match cert {
+        ClientCertificate::PEM {path, private_key} | ClientCertificate::DER {path, private_key} => {
+            (path, private_key.as_ref().map(|key| match key {
+                PrivateKey::PEM {path, ..} | PrivateKey::DER {path, ..} => path,
+            }))
+        },
+        ClientCertificate::P12 {path, ..} => (path, None),
+    };
+
+    if !cert_path.is_file() {
+        return Err(Error::BadClientCertificate(Some(format!("client certificate file not found: {}", cert_path.display()))));
+    }
+
+    if let Some(key_path) = key_path {
+        if !key_path.is_file() {
+            return Err(Error::BadClientCertificate(Some(format!("client private key file not found: {}", key_path.display()))));
+        }
+    }
+
+    Ok(())
 }
 
 /// Helper extension methods for curl easy handles.
@@ -213,33 +842,70 @@ pub struct CurlHandler {
     /// Shared request state.
     state: Arc<RequestState>,
 
+    /// Raw handle of the easy handle this handler belongs to, used to query transfer info.
+    handle: RawHandle,
+
+    /// Timing metrics for the request, if enabled.
+    metrics: Option<Metrics>,
+
     /// Future that resolves when the response headers are received.
     future: Option<oneshot::Sender<Result<Response<CurlResponseStream>, Error>>>,
 
+    /// Method of the request being sent.
+    request_method: http::Method,
+
+    /// Headers of the request being sent.
+    request_headers: http::HeaderMap,
+
     /// A request body to send.
     request_body: Body,
 
+    /// Set if a redirect was rejected by a custom redirect policy and the transfer was aborted because of it.
+    redirect_rejected: bool,
+
+    /// Number of bytes of the response body received so far.
+    response_body_received: u64,
+
     /// Status code of the response.
     status_code: Option<http::StatusCode>,
 
+    /// Reason phrase sent along with the status code, if any.
+    reason_phrase: Option<String>,
+
     /// HTTP version of the response.
     version: Option<http::Version>,
 
     /// Response headers received so far.
     headers: http::HeaderMap,
+
+    /// Number of bytes of the current response body and request body passed to the wire log so far.
+    wire_logged_in: usize,
+    wire_logged_out: usize,
 }
 
 impl CurlHandler {
     /// Mark the request as completed successfully.
     pub fn complete(&mut self) {
+        self.update_metrics();
         self.ensure_future_is_completed();
         self.state.close();
         self.state.read_waker.wake();
     }
 
     /// Fail the request with the given error.
-    pub fn fail(&mut self, error: curl::Error) {
+    pub fn fail(&mut self, mut error: curl::Error) {
+        // Aborting the transfer is how we stop curl from following a rejected redirect, so this is not a failure.
+        if self.redirect_rejected {
+            return self.complete();
+        }
+
+        // If we aborted the transfer because the response body got too large, report that instead of a write error.
+        if self.is_response_too_large() {
+            error = curl::Error::new(curl_sys::CURLE_FILESIZE_EXCEEDED);
+        }
+
         debug_assert!(self.state.error.fill(error).is_ok());
+        self.update_metrics();
 
         // If the future has not been completed yet, complete it with the given error.
         if let Some(future) = self.future.take() {
@@ -272,34 +938,133 @@ impl CurlHandler {
     }
 
     fn is_canceled(&self) -> bool {
-        match self.future {
+        self.state.is_canceled() || match self.future {
             Some(ref future) => future.is_canceled(),
             None => false,
         }
     }
 
+    /// Check if more of the response body has been received than is allowed.
+    fn is_response_too_large(&self) -> bool {
+        match self.state.options.max_response_size {
+            Some(max) => self.response_body_received > max,
+            None => false,
+        }
+    }
+
+    /// Update the request metrics with the latest timings reported by curl.
+    fn update_metrics(&self) {
+        if let Some(metrics) = self.metrics.as_ref() {
+            let get = |info| self.handle.get_duration(info).unwrap_or_default();
+
+            metrics.update(Timings {
+                name_lookup: get(curl_sys::CURLINFO_NAMELOOKUP_TIME),
+                connect: get(curl_sys::CURLINFO_CONNECT_TIME),
+                tls_handshake: get(curl_sys::CURLINFO_APPCONNECT_TIME),
+                start_transfer: get(curl_sys::CURLINFO_STARTTRANSFER_TIME),
+                total: get(curl_sys::CURLINFO_TOTAL_TIME),
+            });
+        }
+    }
+
+    /// Get the addresses of the connection the response is being received on.
+    fn connection_info(&self) -> Option<ConnectionInfo> {
+        let addr = |ip: curl_sys::CURLINFO, port: curl_sys::CURLINFO| {
+            let ip = self.handle.get_str(ip)?.parse::<IpAddr>().ok()?;
+            let port = self.handle.get_long(port)?;
+
+            Some(SocketAddr::new(ip, port as u16))
+        };
+
+        Some(ConnectionInfo {
+            remote_addr: addr(curl_sys::CURLINFO_PRIMARY_IP, curl_sys::CURLINFO_PRIMARY_PORT)?,
+            local_addr: addr(curl_sys::CURLINFO_LOCAL_IP, curl_sys::CURLINFO_LOCAL_PORT)?,
+        })
+    }
+
     /// Determine if curl is about to perform a redirect.
     fn is_about_to_redirect(&self) -> bool {
-        self.state.options.redirect_policy != RedirectPolicy::None
+        let follows_redirects = match self.state.options.redirect_policy {
+            RedirectPolicy::None => false,
+            _ => true,
+        };
+
+        follows_redirects
             && self.status_code.filter(http::StatusCode::is_redirection).is_some()
             && self.headers.contains_key("Location")
     }
 
+    /// Determine if the redirect curl is about to perform is allowed by the redirect policy.
+    fn is_redirect_allowed(&self) -> bool {
+        let policy = match &self.state.options.redirect_policy {
+            RedirectPolicy::Custom(policy) => policy,
+            _ => return true,
+        };
+
+        let current_uri = match self.handle.get_str(curl_sys::CURLINFO_EFFECTIVE_URL).and_then(|url| url.parse().ok()) {
+            Some(uri) => uri,
+            None => return false,
+        };
+
+        let target_uri = match self.headers.get(http::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| parse::resolve_uri(&current_uri, location))
+        {
+            Some(uri) => uri,
+            None => return false,
+        };
+
+        // The request body has already been handed off to curl, so the policy only gets to see the request head.
+        let mut request = http::Request::new(Body::default());
+        *request.method_mut() = self.request_method.clone();
+        *request.uri_mut() = current_uri;
+        *request.headers_mut() = self.request_headers.clone();
+
+        policy(&request, &target_uri)
+    }
+
     /// Completes the associated future when headers have been received.
-    fn finalize_headers(&mut self) {
+    ///
+    /// Returns `false` if the transfer should be aborted.
+    fn finalize_headers(&mut self) -> bool {
         if self.is_about_to_redirect() {
+            if !self.is_redirect_allowed() {
+                debug!("redirect to {:?} rejected by redirect policy", self.headers.get("Location"));
+
+                // Curl has no way of canceling a redirect once it has seen the response, so we complete the future
+                // with the redirect response and abort the transfer.
+                self.ensure_future_is_completed();
+                self.redirect_rejected = true;
+
+                return false;
+            }
+
             debug!("preparing for redirect to {:?}", self.headers.get("Location"));
 
             // It appears that curl will do a redirect, so instead of completing the future, just reset the response
             // state.
             self.status_code = None;
+            self.reason_phrase = None;
             self.version = None;
             self.headers.clear();
 
-            return;
+            return true;
         }
 
         self.ensure_future_is_completed();
+
+        true
+    }
+
+    /// Get the HTTP version used for the response as reported by curl, which is more reliable than the version in the
+    /// status line for HTTP/2 responses.
+    fn negotiated_version(&self) -> Option<http::Version> {
+        match self.handle.get_long(CURLINFO_HTTP_VERSION)? as c_int {
+            curl_sys::CURL_HTTP_VERSION_1_0 => Some(http::Version::HTTP_10),
+            curl_sys::CURL_HTTP_VERSION_1_1 => Some(http::Version::HTTP_11),
+            curl_sys::CURL_HTTP_VERSION_2_0 => Some(http::Version::HTTP_2),
+            _ => None,
+        }
     }
 
     fn ensure_future_is_completed(&mut self) {
@@ -310,7 +1075,38 @@ impl CurlHandler {
 
             let mut builder = http::Response::builder();
             builder.status(self.status_code.take().unwrap());
-            builder.version(self.version.take().unwrap());
+            builder.version(self.negotiated_version().or(self.version.take()).unwrap());
+
+            if let Some(uri) = self.handle.get_str(curl_sys::CURLINFO_EFFECTIVE_URL).and_then(|url| url.parse().ok()) {
+                builder.extension(EffectiveUri(uri));
+            }
+
+            if let Some(reason) = self.reason_phrase.take() {
+                builder.extension(ReasonPhrase(reason));
+            }
+
+            if let Some(count) = self.handle.get_long(curl_sys::CURLINFO_REDIRECT_COUNT) {
+                builder.extension(RedirectCount(count as u32));
+            }
+
+            if let Some(info) = self.connection_info() {
+                builder.extension(info);
+            }
+
+            if let Some(metrics) = self.metrics.as_ref() {
+                self.update_metrics();
+                builder.extension(metrics.clone());
+            }
+
+            // Curl decodes the body for us, so the encoding and length headers no longer describe the body we return.
+            // Responses to HEAD requests have no body, so their headers are left as-is.
+            if self.state.options.decompression_encoding().is_some()
+                && self.request_method != http::Method::HEAD
+                && self.headers.contains_key(http::header::CONTENT_ENCODING)
+            {
+                self.headers.remove(http::header::CONTENT_ENCODING);
+                self.headers.remove(http::header::CONTENT_LENGTH);
+            }
 
             for (name, values) in self.headers.drain() {
                 for value in values {
@@ -334,23 +1130,38 @@ impl curl::easy::Handler for CurlHandler {
         // We need to inspect the contents of the string in order to determine what it is and how to parse it, just as
         // if we were reading from the socket of a HTTP/1.0 or HTTP/1.1 connection ourselves.
 
+        // Don't bother if the request is canceled.
+        if self.is_canceled() {
+            debug!("aborting request, it was canceled");
+            return false;
+        }
+
         // Is this the status line?
-        if let Some((version, status)) = parse::parse_status_line(data) {
+        if let Some((version, status, reason)) = parse::parse_status_line(data) {
+            // Curl reports the headers of every response it receives, such as those of an authentication challenge
+            // or a redirect, so start over with each new response.
             self.version = Some(version);
             self.status_code = Some(status);
+            self.reason_phrase = reason;
+            self.headers.clear();
+
+            // Each response starts a new exchange, possibly with a new request body, so the wire log limits start over.
+            self.wire_logged_in = 0;
+            self.wire_logged_out = 0;
             return true;
         }
 
         // Is this a header line?
+        // Curl passes repeated headers as separate lines, so append them to keep every value rather than only the
+        // last one.
         if let Some((name, value)) = parse::parse_header(data) {
-            self.headers.insert(name, value);
+            self.headers.append(name, value);
             return true;
         }
 
         // Is this the end of the response header?
         if data == b"\r\n" {
-            self.finalize_headers();
-            return true;
+            return self.finalize_headers();
         }
 
         // Unknown header line we don't know how to parse.
@@ -364,9 +1175,22 @@ impl curl::easy::Handler for CurlHandler {
             return Err(curl::easy::ReadError::Abort);
         }
 
-        self.request_body
-            .read(data)
-            .map_err(|_| curl::easy::ReadError::Abort)
+        // Bodies from an asynchronous stream are polled rather than blocked on, since blocking here would stall every
+        // other request on the agent. If no data is ready yet, pause the upload until the stream wakes us up again.
+        let waker = futures::task::waker_ref(&self.state);
+        let mut context = Context::from_waker(&waker);
+
+        match self.request_body.poll_read_nonblocking(&mut context, data) {
+            Poll::Ready(Ok(amount)) => Ok(amount),
+            Poll::Ready(Err(e)) => {
+                debug!("failed to read request body: {}", e);
+                Err(curl::easy::ReadError::Abort)
+            },
+            Poll::Pending => {
+                trace!("request body is not ready, pausing upload");
+                Err(curl::easy::ReadError::Pause)
+            },
+        }
     }
 
     // Gets called by curl when it wants to seek to a certain position in the request body.
@@ -395,6 +1219,14 @@ impl curl::easy::Handler for CurlHandler {
             return Err(curl::easy::WriteError::Pause);
         }
 
+        // Abort the transfer if this would take us over the maximum response size. Curl also checks this against the
+        // content length, but servers may not send one or may send more data than they claim.
+        self.response_body_received += data.len() as u64;
+        if self.is_response_too_large() {
+            debug!("aborting write, response body exceeded max response size");
+            return Ok(0);
+        }
+
         // Store the data in the buffer.
         *buffer = Bytes::from(data);
 
@@ -404,45 +1236,89 @@ impl curl::easy::Handler for CurlHandler {
         Ok(buffer.len())
     }
 
+    // Gets called by curl periodically with the progress of the transfer, if enabled.
+    fn progress(&mut self, dltotal: f64, dlnow: f64, ultotal: f64, ulnow: f64) -> bool {
+        if let Some(handler) = self.state.options.progress.as_ref() {
+            // Curl reports a total of zero if the total is unknown.
+            let total = |total: f64| if total > 0.0 {
+                Some(total as u64)
+            } else {
+                None
+            };
+
+            handler.call(Progress {
+                bytes_sent: ulnow as u64,
+                bytes_received: dlnow as u64,
+                total_upload: total(ultotal),
+                total_download: total(dltotal),
+            });
+        }
+
+        true
+    }
+
     // Gets called by curl whenever it wishes to log a debug message.
     fn debug(&mut self, kind: InfoType, data: &[u8]) {
         match kind {
+            InfoType::Text if self.state.options.verbose => {
+                debug!(target: "chttp::curl", "{}", String::from_utf8_lossy(data).trim_end())
+            },
             InfoType::Text => trace!("{}", String::from_utf8_lossy(data).trim_end()),
-            InfoType::HeaderIn | InfoType::DataIn => trace!(target: "chttp::wire", "<< {}", format_byte_string(data)),
-            InfoType::HeaderOut | InfoType::DataOut => trace!(target: "chttp::wire", ">> {}", format_byte_string(data)),
+            InfoType::HeaderIn => trace!(target: "chttp::wire", "<< {}", format_byte_string(data)),
+            InfoType::DataIn => log_wire_body("<<", data, self.state.options.wire_log_body_limit, &mut self.wire_logged_in),
+            InfoType::HeaderOut => trace!(target: "chttp::wire", ">> {}", format_byte_string(redact_credentials(data))),
+            InfoType::DataOut => log_wire_body(">>", data, self.state.options.wire_log_body_limit, &mut self.wire_logged_out),
             _ => (),
         }
     }
 }
 
-/// Provides an asynchronous stream of the response body for an ongoing request.
-#[derive(Debug)]
-pub struct CurlResponseStream {
-    state: Arc<RequestState>,
-}
-
-// Synchronous wrapper around async stream.
-impl io::Read for CurlResponseStream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        executor::block_on(AsyncReadExt::read(self, buf))
-    }
+/// Log a chunk of body data to the wire log, keeping to the limit for the body as a whole rather than for each chunk.
+fn log_wire_body(direction: &str, data: &[u8], limit: Option<usize>, logged: &mut usize) {
+    match limit {
+        Some(limit) => {
+            let remaining = limit.saturating_sub(*logged);
+            *logged = logged.saturating_add(data.len());
 
-    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
-        executor::block_on(AsyncReadExt::read_exact(self, buf))
+            // Once the limit has been reached, the rest of the body is left out entirely.
+            if remaining > 0 {
+                trace!(target: "chttp::wire", "{} {}", direction, format_body(data, Some(remaining)));
+            }
+        },
+        None => trace!(target: "chttp::wire", "{} {}", direction, format_body(data, None)),
     }
+}
 
-    fn read_to_end(&mut self, dest: &mut Vec<u8>) -> io::Result<usize> {
-        let mut buf = Vec::new();
-        mem::swap(&mut buf, dest);
-
-        match executor::block_on(AsyncReadExt::read_to_end(self, &mut buf)) {
-            Ok(()) => {
-                *dest = buf;
-                Ok(dest.len())
+/// Redact the values of any headers containing credentials from a block of outgoing header data.
+fn redact_credentials(data: &[u8]) -> Vec<u8> {
+    let mut redacted = Vec::with_capacity(data.len());
+    let mut lines = data.split(|byte| *byte == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        match line.iter().position(|byte| *byte == b':') {
+            Some(i) if line[..i].eq_ignore_ascii_case(b"authorization")
+                || line[..i].eq_ignore_ascii_case(b"proxy-authorization") => {
+                redacted.extend_from_slice(&line[..=i]);
+                redacted.extend_from_slice(b" <redacted>");
+                if line.ends_with(b"\r") {
+                    redacted.push(b'\r');
+                }
             },
-            Err(e) => Err(e),
+            _ => redacted.extend_from_slice(line),
+        }
+
+        if lines.peek().is_some() {
+            redacted.push(b'\n');
         }
     }
+
+    redacted
+}
+
+/// Provides an asynchronous stream of the response body for an ongoing request.
+#[derive(Debug)]
+pub struct CurlResponseStream {
+    state: Arc<RequestState>,
 }
 
 impl AsyncRead for CurlResponseStream {
@@ -459,6 +1335,11 @@ impl AsyncRead for CurlResponseStream {
         // Attempt to read some from the buffer.
         let mut buffer = self.state.buffer.lock().unwrap();
 
+        // If the request was canceled, return an error instead of an incomplete body.
+        if self.state.is_canceled() {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, "request was canceled")));
+        }
+
         // If the request failed, return an error.
         if let Some(error) = self.state.error.borrow() {
             debug!("failing read due to error: {:?}", error);
@@ -499,6 +1380,7 @@ impl AsyncRead for CurlResponseStream {
 struct RequestState {
     options: Options,
     status: AtomicUsize,
+    canceled: AtomicBool,
     agent: AtomicLazyCell<agent::Handle>,
     token: AtomicLazyCell<usize>,
     error: AtomicLazyCell<curl::Error>,
@@ -511,6 +1393,7 @@ impl RequestState {
         Self {
             options: options,
             status: AtomicUsize::new(STATUS_READY),
+            canceled: AtomicBool::new(false),
             agent: AtomicLazyCell::new(),
             token: AtomicLazyCell::new(),
             error: AtomicLazyCell::new(),
@@ -526,4 +1409,92 @@ impl RequestState {
     fn close(&self) {
         self.status.store(STATUS_CLOSED, Ordering::SeqCst);
     }
+
+    fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
+
+    /// Cancel the request, removing it from the agent if it has already started.
+    fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+
+        if let Some(agent) = self.agent.borrow() {
+            if let Some(token) = self.token.get() {
+                if agent.cancel_request(token).is_err() {
+                    warn!("failed to cancel request {}", token);
+                }
+            }
+        }
+
+        self.close();
+        self.read_waker.wake();
+    }
+}
+
+/// Waking the request state resumes a paused upload once an asynchronous request body has more data to send.
+impl ArcWake for RequestState {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(agent) = arc_self.agent.borrow() {
+            if let Some(token) = arc_self.token.get() {
+                if agent.unpause_read(token).is_err() {
+                    warn!("failed to resume upload of request {}", token);
+                }
+            }
+        }
+    }
+}
+
+/// A signal for canceling a request in progress, shared between the request and its owner.
+///
+/// Since middleware may send a request more than once, all of the transfers made for the request are canceled together.
+/// Requests pick up a cancellation from their extensions when they are created.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation(Arc<CancellationInner>);
+
+#[derive(Debug, Default)]
+struct CancellationInner {
+    canceled: AtomicBool,
+    waker: AtomicWaker,
+    requests: Mutex<Vec<Weak<RequestState>>>,
+}
+
+impl Cancellation {
+    /// Cancel any transfers in progress, as well as any that are started afterwards.
+    pub fn cancel(&self) {
+        let requests = {
+            let mut requests = self.0.requests.lock().unwrap();
+            self.0.canceled.store(true, Ordering::SeqCst);
+            requests.split_off(0)
+        };
+
+        for state in requests.iter().filter_map(Weak::upgrade) {
+            state.cancel();
+        }
+
+        self.0.waker.wake();
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.canceled.load(Ordering::SeqCst)
+    }
+
+    /// Register a task to wake up when canceled.
+    pub fn register(&self, waker: &std::task::Waker) {
+        self.0.waker.register(waker);
+    }
+
+    /// Add a transfer to be canceled. Returns `false` if the cancellation has already happened.
+    fn register_request(&self, state: &Arc<RequestState>) -> bool {
+        let mut requests = self.0.requests.lock().unwrap();
+
+        if self.is_canceled() {
+            return false;
+        }
+
+        // Forget about any transfers that have already finished.
+        requests.retain(|request| request.upgrade().is_some());
+        requests.push(Arc::downgrade(state));
+
+        true
+    }
 }
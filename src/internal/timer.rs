@@ -0,0 +1,127 @@
+//! A shared timer for futures that need to wait for a while.
+
+use futures::channel::oneshot;
+use futures::prelude::*;
+use lazy_static::lazy_static;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMER_THREAD_NAME: &'static str = "chttp timer";
+
+lazy_static! {
+    static ref TIMER: Arc<Timer> = Timer::start();
+}
+
+/// Create a future that completes after the given duration has passed.
+///
+/// All pending delays are tracked by a single background thread, so waiting neither blocks the current task, which may
+/// be shared with other requests, nor requires a thread of its own.
+pub fn delay(duration: Duration) -> impl Future<Output=()> {
+    let (tx, rx) = oneshot::channel();
+
+    TIMER.schedule(Instant::now() + duration, tx);
+
+    rx.map(|_| ())
+}
+
+struct Timer {
+    entries: Mutex<BinaryHeap<Entry>>,
+    condvar: Condvar,
+}
+
+impl Timer {
+    fn start() -> Arc<Self> {
+        let timer = Arc::new(Self {
+            entries: Mutex::new(BinaryHeap::new()),
+            condvar: Condvar::new(),
+        });
+
+        let thread_timer = timer.clone();
+        thread::Builder::new()
+            .name(String::from(TIMER_THREAD_NAME))
+            .spawn(move || thread_timer.run())
+            .expect("failed to start timer thread");
+
+        timer
+    }
+
+    fn schedule(&self, deadline: Instant, sender: oneshot::Sender<()>) {
+        self.entries.lock().unwrap().push(Entry {
+            deadline,
+            sender,
+        });
+
+        // The new entry may be due before the one the timer thread is currently waiting for.
+        self.condvar.notify_one();
+    }
+
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+
+            // Delays whose future has been dropped are completed all the same, which just discards them.
+            while entries.peek().map_or(false, |entry| entry.deadline <= now) {
+                let _ = entries.pop().unwrap().sender.send(());
+            }
+
+            let next_deadline = entries.peek().map(|entry| entry.deadline);
+
+            entries = match next_deadline {
+                Some(deadline) => self.condvar.wait_timeout(entries, deadline - now).unwrap().0,
+                None => self.condvar.wait(entries).unwrap(),
+            };
+        }
+    }
+}
+
+/// A pending delay, ordered so that the earliest deadline is at the top of the heap.
+struct Entry {
+    deadline: Instant,
+    sender: oneshot::Sender<()>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_complete_in_deadline_order() {
+        let start = Instant::now();
+        let long = delay(Duration::from_millis(200));
+        let short = delay(Duration::from_millis(50));
+
+        futures::executor::block_on(short);
+        let short_elapsed = start.elapsed();
+        futures::executor::block_on(long);
+        let long_elapsed = start.elapsed();
+
+        assert!(short_elapsed >= Duration::from_millis(50));
+        assert!(short_elapsed < Duration::from_millis(200));
+        assert!(long_elapsed >= Duration::from_millis(200));
+    }
+}
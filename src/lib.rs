@@ -100,7 +100,12 @@
 //!
 //! If you set the log level to `Trace` for the `chttp::wire` target, cHTTP will also log all incoming and outgoing data
 //! while in flight. This may come in handy if you are debugging code and need to see the exact data being sent to the
-//! server and being received.
+//! server and being received. Body data that is not valid UTF-8 is logged as a hex dump, and large bodies can be
+//! truncated using the [`wire_log_body_limit`](options/struct.Options.html#structfield.wire_log_body_limit) option.
+//!
+//! Enabling the [`verbose`](options/struct.Options.html#structfield.verbose) option logs libcurl's own diagnostic
+//! messages at the `Debug` level to the `chttp::curl` target, which can help explain why connections are not being
+//! reused.
 //!
 //! ## Feature flags
 //!
@@ -122,40 +127,65 @@
 //!
 //! ### `http2`
 //!
-//! Enable HTTP/2 support in libcurl via libnghttp2. Enabled by default.
+//! Enable HTTP/2 support in libcurl via libnghttp2. Enabled by default. Server push is not supported, and any streams
+//! pushed by a server are refused.
 //!
 //! ### `json`
 //!
-//! Enable convenience methods for parsing HTTP responses into JSON objects. Disabled by default.
+//! Enable convenience methods for deserializing HTTP responses from JSON using [serde]. Disabled by default.
 //!
 //! ### `psl`
 //!
 //! Enable use of the Public Suffix List to filter out potentially malicious cross-domain cookies. Enabled by default.
 //!
+//! ### `text-decoding`
+//!
+//! Enable [`ResponseExt::text`](response/trait.ResponseExt.html#method.text) for decoding response bodies using the
+//! character encoding given in the `Content-Type` header, using [encoding_rs]. Disabled by default.
+//!
 //! ### `async-api`
 //!
-//! Enable the async futures-based API. This allows you to take full advantage of cHTTP's asynchronous core. Currently
-//! behind a feature flag until the futures API stabilizes. This an unstable feature whose interface may change between
-//! patch releases.
+//! Enable the async futures-based API. This allows you to take full advantage of cHTTP's asynchronous core. Responses
+//! are returned as standard library futures, which can be `.await`ed and used with any executor. This an unstable
+//! feature whose interface may change between patch releases.
 //!
 //! ### `middleware-api`
 //!
 //! Enable the new middleware API. Unstable until the API is finalized. This an unstable feature whose interface may
 //! change between patch releases.
 //!
+//! ### `mock`
+//!
+//! Enable the [`mock`](mock/index.html) module, which allows clients to return canned responses instead of sending
+//! requests over the network, for use in tests. Disabled by default.
+//!
+//! ### `sse`
+//!
+//! Enable the [`sse`](sse/index.html) module for parsing server-sent events from `text/event-stream` responses.
+//! Disabled by default.
+//!
+//! [encoding_rs]: https://docs.rs/encoding_rs
 //! [libcurl]: https://curl.haxx.se/libcurl/
 //! [log]: https://docs.rs/log
-
-#![feature(futures_api)]
+//! [serde]: https://serde.rs
 
 pub mod body;
 pub mod client;
 pub mod error;
+pub mod metrics;
 pub mod options;
+pub mod resolver;
+pub mod response;
 
 #[cfg(feature = "cookies")]
 pub mod cookies;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "sse")]
+pub mod sse;
+
 #[cfg(feature = "middleware-api")]
 pub mod middleware;
 #[cfg(not(feature = "middleware-api"))]
@@ -169,7 +199,9 @@ pub use http;
 pub use crate::body::Body;
 pub use crate::client::Client;
 pub use crate::error::Error;
+pub use crate::metrics::{ClientMetrics, Metrics};
 pub use crate::options::*;
+pub use crate::response::{ConnectionInfo, ResponseExt};
 
 
 /// An HTTP request.
@@ -179,6 +211,28 @@ pub type Request = http::Request<Body>;
 pub type Response = http::Response<Body>;
 
 
+/// Sets the default options of the global client used by the functions in this module, such as [`get`](fn.get.html).
+///
+/// The global client is created the first time it is used, after which its options can no longer be changed, so this
+/// must be called before sending any requests using these functions. Otherwise,
+/// [`Error::AlreadyInitialized`](error/enum.Error.html#variant.AlreadyInitialized) is returned. Calling it more than
+/// once before then replaces the options given previously.
+///
+/// ```rust
+/// use chttp::Options;
+/// use std::time::Duration;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// chttp::configure(Options::default().with_timeout(Some(Duration::from_secs(10))))?;
+///
+/// let response = chttp::get("https://example.org")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn configure(options: Options) -> Result<(), Error> {
+    client::configure_global(options)
+}
+
 /// Sends an HTTP GET request.
 ///
 /// The response body is provided as a stream that may only be consumed once.
@@ -186,6 +240,19 @@ pub fn get<U>(uri: U) -> Result<Response, Error> where http::Uri: http::HttpTryF
     client::global().get(uri)
 }
 
+/// Sends an HTTP GET request and deserializes the JSON response body into a value of type `T`.
+///
+/// If the server responds with a status code other than `2xx`, the body is not parsed, and
+/// [`Error::Status`](error/enum.Error.html#variant.Status) is returned instead.
+#[cfg(feature = "json")]
+pub fn get_json<T, U>(uri: U) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+    http::Uri: http::HttpTryFrom<U>,
+{
+    client::global().get_json(uri)
+}
+
 /// Sends an HTTP HEAD request.
 pub fn head<U>(uri: U) -> Result<Response, Error> where http::Uri: http::HttpTryFrom<U> {
     client::global().head(uri)
@@ -198,6 +265,22 @@ pub fn post<U>(uri: U, body: impl Into<Body>) -> Result<Response, Error> where h
     client::global().post(uri, body)
 }
 
+/// Sends an HTTP POST request with a value serialized as JSON as the request body.
+///
+/// The `Content-Type` header is set to `application/json`. If the value cannot be serialized,
+/// [`Error::SerializationFailed`](error/enum.Error.html#variant.SerializationFailed) is returned without sending
+/// anything.
+///
+/// The response body is provided as a stream that may only be consumed once.
+#[cfg(feature = "json")]
+pub fn post_json<T, U>(uri: U, body: &T) -> Result<Response, Error>
+where
+    T: serde::Serialize + ?Sized,
+    http::Uri: http::HttpTryFrom<U>,
+{
+    client::global().post_json(uri, body)
+}
+
 /// Sends an HTTP PUT request.
 ///
 /// The response body is provided as a stream that may only be consumed once.
@@ -205,6 +288,13 @@ pub fn put<U>(uri: U, body: impl Into<Body>) -> Result<Response, Error> where ht
     client::global().put(uri, body)
 }
 
+/// Sends an HTTP PATCH request.
+///
+/// The response body is provided as a stream that may only be consumed once.
+pub fn patch<U>(uri: U, body: impl Into<Body>) -> Result<Response, Error> where http::Uri: http::HttpTryFrom<U> {
+    client::global().patch(uri, body)
+}
+
 /// Sends an HTTP DELETE request.
 ///
 /// The response body is provided as a stream that may only be consumed once.
@@ -0,0 +1,128 @@
+//! Timing metrics for individual requests and usage statistics for clients.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A breakdown of the time spent in the various phases of a request.
+///
+/// Metrics are only collected for requests with the [`metrics`](../options/struct.Options.html#structfield.metrics)
+/// option enabled, in which case they are attached to the response as an extension:
+///
+/// ```rust
+/// use chttp::{http, Metrics, Options};
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let request = http::Request::get("https://example.org")
+///     .extension(Options::default().with_metrics(true))
+///     .body(())?;
+/// let response = chttp::send(request)?;
+///
+/// if let Some(metrics) = response.extensions().get::<Metrics>() {
+///     println!("time to first byte: {:?}", metrics.time_to_first_byte());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Since the response is returned as soon as the response headers are received, the metrics are shared with the
+/// ongoing transfer and updated once more when the response body has been fully received. This is mostly relevant to
+/// [`total_time`](#method.total_time), which continues to grow until the transfer is complete.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Timings>>,
+}
+
+/// Cumulative timings reported by curl, each measured from the start of the request.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Timings {
+    pub(crate) name_lookup: Duration,
+    pub(crate) connect: Duration,
+    pub(crate) tls_handshake: Duration,
+    pub(crate) start_transfer: Duration,
+    pub(crate) total: Duration,
+}
+
+impl Metrics {
+    /// Time spent resolving the host name.
+    pub fn name_lookup_time(&self) -> Duration {
+        self.timings().name_lookup
+    }
+
+    /// Time spent establishing the TCP connection to the server, excluding name resolution.
+    pub fn connect_time(&self) -> Duration {
+        let timings = self.timings();
+        sub(timings.connect, timings.name_lookup)
+    }
+
+    /// Time spent performing the TLS handshake, if any.
+    pub fn tls_handshake_time(&self) -> Duration {
+        let timings = self.timings();
+
+        // Curl reports zero if no TLS handshake was performed.
+        if timings.tls_handshake == Duration::default() {
+            Duration::default()
+        } else {
+            sub(timings.tls_handshake, timings.connect)
+        }
+    }
+
+    /// Time from the start of the request until the first byte of the response was received.
+    pub fn time_to_first_byte(&self) -> Duration {
+        self.timings().start_transfer
+    }
+
+    /// Total time taken by the request, including receiving the response body.
+    ///
+    /// If the response body is still being received, this is the time taken so far.
+    pub fn total_time(&self) -> Duration {
+        self.timings().total
+    }
+
+    pub(crate) fn update(&self, timings: Timings) {
+        *self.inner.lock().unwrap() = timings;
+    }
+
+    fn timings(&self) -> Timings {
+        *self.inner.lock().unwrap()
+    }
+}
+
+/// A snapshot of usage statistics of a [`Client`](../client/struct.Client.html).
+///
+/// Returned by [`Client::metrics`](../client/struct.Client.html#method.metrics). The counters are maintained by the
+/// client's event loop and are cheap to query, which makes them suitable for periodic reporting. Note that libcurl does
+/// not expose the number of idle connections in its connection pool, so only connection openings are counted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ClientMetrics {
+    pub(crate) requests_sent: usize,
+    pub(crate) active_requests: usize,
+    pub(crate) connections_opened: usize,
+}
+
+impl ClientMetrics {
+    /// Total number of requests the client has started sending.
+    pub fn requests_sent(&self) -> usize {
+        self.requests_sent
+    }
+
+    /// Number of requests that are currently in progress.
+    ///
+    /// A request is in progress until its response body has been fully received or the request has failed or been
+    /// canceled.
+    pub fn active_requests(&self) -> usize {
+        self.active_requests
+    }
+
+    /// Total number of new connections the client has opened.
+    ///
+    /// Comparing this to [`requests_sent`](#method.requests_sent) gives an indication of how well connections are being
+    /// reused.
+    pub fn connections_opened(&self) -> usize {
+        self.connections_opened
+    }
+}
+
+/// Subtract two durations, saturating at zero.
+fn sub(a: Duration, b: Duration) -> Duration {
+    a.checked_sub(b).unwrap_or_default()
+}
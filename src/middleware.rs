@@ -3,9 +3,36 @@
 //! This module provides the core types and functions for defining and working with middleware. Middleware are handlers
 //! that augment HTTP client functionality by applying transformations to HTTP requests before they are sent and/or HTTP
 //! responses after they are received.
+//!
+//! Middleware that need more control over how a request is sent, such as sending it more than once, can override
+//! [`Middleware::send`](trait.Middleware.html#method.send) to intercept the request and pass it along to the rest of
+//! the middleware chain using [`Next`](struct.Next.html).
 
+use crate::Body;
+use crate::Error;
 use crate::Request;
 use crate::Response;
+use futures::future::BoxFuture;
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(feature = "middleware-api")]
+mod cache;
+#[cfg(feature = "middleware-api")]
+mod idempotency;
+#[cfg(feature = "middleware-api")]
+mod rate_limit;
+#[cfg(feature = "middleware-api")]
+mod retry;
+
+#[cfg(feature = "middleware-api")]
+pub use self::cache::{CacheMiddleware, CacheStorage, CachedResponse, MemoryStorage};
+#[cfg(feature = "middleware-api")]
+pub use self::idempotency::IdempotencyMiddleware;
+#[cfg(feature = "middleware-api")]
+pub use self::rate_limit::RateLimitMiddleware;
+#[cfg(feature = "middleware-api")]
+pub use self::retry::RetryMiddleware;
 
 /// Create a new _request_ middleware from a function.
 #[allow(unused)]
@@ -57,6 +84,96 @@ pub trait Middleware: Send + Sync + 'static {
     fn filter_response(&self, response: Response) -> Response {
         response
     }
+
+    /// Transform the body of a response after it is received.
+    ///
+    /// This is useful for middleware that decode or reframe the body stream, since the returned body can wrap the
+    /// original one and transform it as it is being read, without buffering it in memory first:
+    ///
+    /// ```rust
+    /// use chttp::Body;
+    /// use chttp::middleware::Middleware;
+    /// use std::io::{self, Read};
+    ///
+    /// struct Uppercase<R>(R);
+    ///
+    /// impl<R: Read> Read for Uppercase<R> {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         let amount = self.0.read(buf)?;
+    ///         buf[..amount].make_ascii_uppercase();
+    ///         Ok(amount)
+    ///     }
+    /// }
+    ///
+    /// struct UppercaseMiddleware;
+    ///
+    /// impl Middleware for UppercaseMiddleware {
+    ///     fn filter_body(&self, body: Body) -> Body {
+    ///         Body::from_reader(Uppercase(body))
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Like response filters, body filters are applied starting with the innermost middleware (the one added first).
+    /// The body filter of a middleware is applied right before its response filter.
+    fn filter_body(&self, body: Body) -> Body {
+        body
+    }
+
+    /// Intercept sending a request, returning a future of the response.
+    ///
+    /// This is called after all request filters have been applied, and before any response filters are applied. The
+    /// default implementation passes the request along to the next middleware unchanged. Implementations may call
+    /// [`Next::send`](struct.Next.html#method.send) more than once, or not at all.
+    ///
+    /// Like request filters, the outermost middleware (the one added last) intercepts the request first.
+    fn send(&self, request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        next.send(request)
+    }
+}
+
+/// The function that actually sends a request once it has passed through all middleware.
+pub(crate) type Transport = Arc<dyn Fn(Request) -> BoxFuture<'static, Result<Response, Error>> + Send + Sync>;
+
+/// The remainder of a middleware chain, which a middleware can use to send a request onwards.
+#[derive(Clone)]
+pub struct Next {
+    chain: Arc<Vec<Box<dyn Middleware>>>,
+    index: usize,
+    transport: Transport,
+}
+
+impl Next {
+    /// Create the start of a middleware chain.
+    pub(crate) fn new(chain: Arc<Vec<Box<dyn Middleware>>>, transport: Transport) -> Self {
+        let index = chain.len();
+
+        Self {
+            chain,
+            index,
+            transport,
+        }
+    }
+
+    /// Send a request through the rest of the middleware chain, returning a future of the response.
+    pub fn send(&self, request: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        match self.index.checked_sub(1) {
+            Some(index) => self.chain[index].send(request, Next {
+                chain: self.chain.clone(),
+                index,
+                transport: self.transport.clone(),
+            }),
+            None => (self.transport)(request),
+        }
+    }
+}
+
+impl fmt::Debug for Next {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Next")
+            .field("remaining", &self.index)
+            .finish()
+    }
 }
 
 /// The identity function. Here for convenience.
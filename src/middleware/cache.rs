@@ -0,0 +1,374 @@
+//! Middleware for caching responses according to HTTP caching headers.
+
+use crate::{Body, Error, Request, Response};
+use crate::middleware::{Middleware, Next};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::{Method, StatusCode};
+use log::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A response stored in a cache.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    /// The status code of the response.
+    pub status: StatusCode,
+
+    /// The headers of the response.
+    pub headers: HeaderMap,
+
+    /// The complete body of the response.
+    pub body: Vec<u8>,
+
+    /// The request headers named by the response's `Vary` header, along with their values in the request that produced
+    /// the response. A stored response is only used for requests with the same values for these headers.
+    pub vary: Vec<(HeaderName, Option<HeaderValue>)>,
+
+    /// When the response was received or last revalidated.
+    pub stored_at: SystemTime,
+}
+
+/// A place where cached responses are stored.
+///
+/// Implement this trait to back a [`CacheMiddleware`](struct.CacheMiddleware.html) with your own storage, such as a
+/// directory on disk. Since clients may be used to send requests concurrently, implementations must be synchronized.
+pub trait CacheStorage: Send + Sync + 'static {
+    /// Get the response stored under the given key, if any.
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// Store a response under the given key, replacing any existing response.
+    fn put(&self, key: &str, response: CachedResponse);
+
+    /// Remove the response stored under the given key, if any.
+    fn remove(&self, key: &str);
+}
+
+/// A cache storage that keeps responses in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    responses: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.responses.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        self.responses.lock().unwrap().insert(key.to_owned(), response);
+    }
+
+    fn remove(&self, key: &str) {
+        self.responses.lock().unwrap().remove(key);
+    }
+}
+
+/// A middleware that caches responses to `GET` requests according to their `Cache-Control`, `ETag` and
+/// `Last-Modified` headers.
+///
+/// Responses are served from the cache without contacting the server while they are fresh according to their
+/// `Cache-Control: max-age` directive. Once stale, they are revalidated using a conditional request with
+/// `If-None-Match` or `If-Modified-Since`, and a `304 Not Modified` response is turned back into the cached response.
+/// Responses marked `Cache-Control: no-store` are never stored. Responses with a `Vary` header are stored separately
+/// for each combination of values of the request headers it names.
+///
+/// A response is only stored once its body has been read to the end, since the whole body must be kept. Responses
+/// served from the cache, including those revalidated with a `304 Not Modified` response, do not have any response
+/// extensions, such as the effective URI or the reason phrase. Requests that already include `If-None-Match` or
+/// `If-Modified-Since` are passed on as-is, so that the caller receives the `304 Not Modified` response they asked
+/// for.
+///
+/// ```rust
+/// use chttp::Client;
+/// use chttp::middleware::CacheMiddleware;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let client = Client::builder()
+///     .with_middleware(CacheMiddleware::new())
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CacheMiddleware {
+    storage: Arc<dyn CacheStorage>,
+}
+
+impl Default for CacheMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheMiddleware {
+    /// Create a new cache middleware that stores responses in memory.
+    pub fn new() -> Self {
+        Self::with_storage(MemoryStorage::default())
+    }
+
+    /// Create a new cache middleware that stores responses in the given storage.
+    pub fn with_storage(storage: impl CacheStorage) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+}
+
+impl Middleware for CacheMiddleware {
+    fn send(&self, mut request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        if request.method() != Method::GET {
+            return next.send(request);
+        }
+
+        let base_key = request.uri().to_string();
+        let request_headers = request.headers().clone();
+
+        // A response that varies on request headers is stored under a key including their values, while the entry for
+        // the URI itself keeps the names of the headers to look for.
+        let mut key = base_key.clone();
+        let cached = match self.storage.get(&base_key) {
+            Some(ref index) if !index.vary.is_empty() => {
+                key = variant_key(&base_key, index.vary.iter().map(|(name, _)| name), &request_headers);
+                self.storage.get(&key)
+            },
+            cached => cached,
+        };
+
+        // A caller sending a conditional request of their own expects to see a `304 Not Modified` response as-is, so
+        // the stored response is only used for requests that the middleware can make conditional itself.
+        let caller_is_conditional = request.headers().contains_key(header::IF_NONE_MATCH)
+            || request.headers().contains_key(header::IF_MODIFIED_SINCE);
+        let cached = cached.filter(|cached| !caller_is_conditional && cached.matches(&request));
+
+        if let Some(cached) = cached.as_ref() {
+            if cached.is_fresh() {
+                debug!("serving fresh response for {} from cache", base_key);
+                return future::ready(Ok(cached.clone().into_response())).boxed();
+            }
+
+            // The cached response is stale, so ask the server whether it is still valid.
+            if let Some(etag) = cached.headers.get(header::ETAG) {
+                request.headers_mut().insert(header::IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = cached.headers.get(header::LAST_MODIFIED) {
+                request.headers_mut().insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let storage = self.storage.clone();
+
+        next.send(request).map(move |result| result.map(|response| {
+            if response.status() == StatusCode::NOT_MODIFIED {
+                let mut cached = match cached {
+                    Some(cached) => cached,
+                    // The validators came from the caller, so the response is returned to them unchanged.
+                    None => return response,
+                };
+
+                debug!("cached response for {} revalidated", base_key);
+
+                // Update the stored headers with any new values sent with the 304 response, except for those
+                // describing the empty body of the 304 response itself.
+                for (name, value) in response.headers() {
+                    if name != header::CONTENT_LENGTH && name != header::TRANSFER_ENCODING {
+                        cached.headers.insert(name, value.clone());
+                    }
+                }
+                cached.stored_at = SystemTime::now();
+                storage.put(&key, cached.clone());
+
+                return cached.into_response();
+            }
+
+            if !is_storable(&response) {
+                storage.remove(&key);
+                return response;
+            }
+
+            let (parts, body) = response.into_parts();
+            let len = body.len();
+            let status = parts.status;
+            let headers = parts.headers.clone();
+            let vary: Vec<_> = vary_names(&headers)
+                .into_iter()
+                .map(|name| {
+                    let value = request_headers.get(&name).cloned();
+                    (name, value)
+                })
+                .collect();
+
+            // Store the response once its body has been read completely.
+            let reader = TeeReader {
+                inner: body,
+                buffer: Vec::new(),
+                on_complete: Some(Box::new(move |body| {
+                    let response = CachedResponse {
+                        status,
+                        headers,
+                        body,
+                        vary,
+                        stored_at: SystemTime::now(),
+                    };
+
+                    if response.vary.is_empty() {
+                        storage.put(&base_key, response);
+                    } else {
+                        let key = variant_key(&base_key, response.vary.iter().map(|(name, _)| name), &request_headers);
+
+                        // The entry for the URI is only used to find the variant, so it does not need the body.
+                        storage.put(&base_key, CachedResponse {
+                            body: Vec::new(),
+                            ..response.clone()
+                        });
+                        storage.put(&key, response);
+                    }
+                })),
+            };
+
+            let body = match len {
+                Some(len) => Body::from_reader_sized(reader, len),
+                None => Body::from_reader(reader),
+            };
+
+            Response::from_parts(parts, body)
+        })).boxed()
+    }
+}
+
+impl fmt::Debug for CacheMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CacheMiddleware")
+    }
+}
+
+impl CachedResponse {
+    /// Check if the response can be used for the given request based on the response's `Vary` header.
+    fn matches(&self, request: &Request) -> bool {
+        self.vary.iter().all(|(name, value)| request.headers().get(name) == value.as_ref())
+    }
+
+    /// Check if the response can be used without revalidating it with the server.
+    fn is_fresh(&self) -> bool {
+        match cache_control(&self.headers).max_age {
+            Some(max_age) => self.stored_at.elapsed().map(|age| age < max_age).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Create a response from the stored one. Response extensions are not stored, so the response has none.
+    pub(crate) fn into_response(self) -> Response {
+        let mut response = http::Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+/// Caching directives from a `Cache-Control` header.
+#[derive(Default)]
+struct CacheControl {
+    max_age: Option<Duration>,
+    no_cache: bool,
+    no_store: bool,
+}
+
+fn cache_control(headers: &HeaderMap) -> CacheControl {
+    let mut directives = CacheControl::default();
+
+    for value in headers.get_all(header::CACHE_CONTROL).iter().filter_map(|value| value.to_str().ok()) {
+        for directive in value.split(',').map(str::trim) {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next().unwrap_or_default();
+
+            if name.eq_ignore_ascii_case("max-age") {
+                directives.max_age = parts.next()
+                    .and_then(|seconds| seconds.trim_matches('"').parse().ok())
+                    .map(Duration::from_secs);
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                directives.no_cache = true;
+            } else if name.eq_ignore_ascii_case("no-store") {
+                directives.no_store = true;
+            }
+        }
+    }
+
+    // A response that must be revalidated every time is never fresh.
+    if directives.no_cache {
+        directives.max_age = None;
+    }
+
+    directives
+}
+
+/// Check if a response may be stored in the cache.
+fn is_storable(response: &Response) -> bool {
+    if response.status() != StatusCode::OK || cache_control(response.headers()).no_store {
+        return false;
+    }
+
+    // A response that varies on anything can never be matched again.
+    if response.headers().get_all(header::VARY).iter().any(|value| value == "*") {
+        return false;
+    }
+
+    // Only bother storing responses that can either be reused directly or revalidated.
+    cache_control(response.headers()).max_age.is_some()
+        || response.headers().contains_key(header::ETAG)
+        || response.headers().contains_key(header::LAST_MODIFIED)
+}
+
+/// Get the names of the request headers listed in a response's `Vary` header.
+fn vary_names(headers: &HeaderMap) -> Vec<HeaderName> {
+    headers.get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect()
+}
+
+/// Get the key for a response to a request with the given headers, which varies on the headers with the given names.
+fn variant_key<'a>(key: &str, names: impl Iterator<Item = &'a HeaderName>, headers: &HeaderMap) -> String {
+    let mut key = key.to_owned();
+
+    // Header values cannot contain line breaks, so each header gets a line of its own.
+    for name in names {
+        key.push('\n');
+        key.push_str(name.as_str());
+
+        for value in headers.get_all(name) {
+            key.push_str(": ");
+            key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+        }
+    }
+
+    key
+}
+
+/// Reads from a body while keeping a copy of everything read, and hands off the copy once the end is reached.
+pub(crate) struct TeeReader {
+    pub(crate) inner: Body,
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) on_complete: Option<Box<dyn FnOnce(Vec<u8>) + Send>>,
+}
+
+impl Read for TeeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.inner.read(buf)?;
+
+        if amount > 0 {
+            self.buffer.extend_from_slice(&buf[..amount]);
+        } else if !buf.is_empty() {
+            if let Some(on_complete) = self.on_complete.take() {
+                on_complete(mem::replace(&mut self.buffer, Vec::new()));
+            }
+        }
+
+        Ok(amount)
+    }
+}
@@ -0,0 +1,256 @@
+//! Middleware for attaching idempotency keys to requests and deduplicating repeated requests.
+
+use crate::{Body, Error, Request, Response};
+use crate::internal;
+use crate::middleware::{Middleware, Next};
+use crate::middleware::cache::{CachedResponse, TeeReader};
+use futures::channel::oneshot;
+use futures::future::{BoxFuture, Shared};
+use futures::prelude::*;
+use http::header::{HeaderName, HeaderValue};
+use http::{Method, Uri};
+use log::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A future that resolves to the response of the original request with a given key, or `None` if it failed.
+type PendingResponse = Shared<BoxFuture<'static, Option<Arc<CachedResponse>>>>;
+
+/// Identifies the original request that a repeated request may be answered from.
+type EntryKey = (Method, Uri, HeaderValue);
+
+/// A middleware that attaches an `Idempotency-Key` header to requests that are not idempotent, and avoids sending the
+/// same request more than once.
+///
+/// `POST` and `PATCH` requests without an `Idempotency-Key` header get a new random key. Requests that already have a
+/// key are treated as repeats of any earlier request with the same method, URI and key: while the earlier request is
+/// waiting for a response, the repeat waits for it to complete and returns a copy of its response instead of being
+/// sent. Copies of the response are also returned for repeats within a configurable window after the response has been
+/// received.
+///
+/// The response can only be copied once its body has been read to the end. If the original request fails, or its
+/// response body is not read completely, repeats are sent as usual. Repeats made after the response has been received
+/// but before its body has been read are sent as usual as well, since the caller may be holding on to the unread
+/// response while making them. The server can still recognize such repeats by their key.
+///
+/// ```rust
+/// use chttp::Client;
+/// use chttp::middleware::IdempotencyMiddleware;
+/// use std::time::Duration;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let client = Client::builder()
+///     .with_middleware(IdempotencyMiddleware::new().window(Duration::from_secs(60)))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct IdempotencyMiddleware {
+    header: HeaderName,
+    window: Duration,
+    entries: Arc<Mutex<HashMap<EntryKey, Entry>>>,
+}
+
+/// The state of the original request sent with a given key.
+#[derive(Clone)]
+enum Entry {
+    /// The request has been sent, but no response has been received yet.
+    InFlight(PendingResponse),
+    /// The response has been received, but its body has not been read completely yet.
+    Receiving,
+    /// The response has been received completely, and may be returned for repeats until the given time.
+    Complete(Arc<CachedResponse>, Instant),
+}
+
+impl Default for IdempotencyMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdempotencyMiddleware {
+    /// Create a new idempotency middleware that uses the `Idempotency-Key` header.
+    pub fn new() -> Self {
+        Self {
+            header: HeaderName::from_static("idempotency-key"),
+            window: Duration::from_secs(0),
+            entries: Arc::default(),
+        }
+    }
+
+    /// Set the name of the header to send the key in.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = name;
+        self
+    }
+
+    /// Set how long after a response has been received that repeated requests are answered with a copy of it.
+    ///
+    /// The default is zero, which only deduplicates requests that are sent while the original is still in progress.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+impl Middleware for IdempotencyMiddleware {
+    fn send(&self, mut request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        if request.method() != Method::POST && request.method() != Method::PATCH {
+            return next.send(request);
+        }
+
+        let key = request.headers_mut()
+            .entry(&self.header)
+            .unwrap()
+            .or_insert_with(generate_key)
+            .clone();
+
+        let entry_key = (request.method().clone(), request.uri().clone(), key.clone());
+
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| match entry {
+            Entry::Complete(_, expires) => *expires > now,
+            _ => true,
+        });
+
+        match entries.get(&entry_key).cloned() {
+            Some(Entry::Complete(response, _)) => {
+                drop(entries);
+                debug!("returning stored response for repeated request with key {:?}", key);
+                return future::ready(Ok(CachedResponse::clone(&response).into_response())).boxed();
+            },
+            Some(Entry::InFlight(pending)) => {
+                drop(entries);
+                debug!("waiting for response to original request with key {:?}", key);
+
+                return async move {
+                    match pending.await {
+                        Some(response) => Ok(CachedResponse::clone(&response).into_response()),
+                        None => next.send(request).await,
+                    }
+                }.boxed();
+            },
+            Some(Entry::Receiving) => {
+                drop(entries);
+                debug!("response to original request with key {:?} is still being read, sending repeated request", key);
+                return next.send(request);
+            },
+            None => {},
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let pending = receiver.map(Result::ok).boxed().shared();
+        entries.insert(entry_key.clone(), Entry::InFlight(pending));
+        drop(entries);
+
+        let publisher = Publisher {
+            key: entry_key,
+            window: self.window,
+            entries: self.entries.clone(),
+            sender: Some(sender),
+        };
+
+        next.send(request).map(move |result| result.map(|response| {
+            publisher.receiving();
+
+            let (parts, body) = response.into_parts();
+            let len = body.len();
+            let status = parts.status;
+            let headers = parts.headers.clone();
+
+            // Share the response once its body has been read completely.
+            let reader = TeeReader {
+                inner: body,
+                buffer: Vec::new(),
+                on_complete: Some(Box::new(move |body| {
+                    publisher.publish(CachedResponse {
+                        status,
+                        headers,
+                        body,
+                        vary: Vec::new(),
+                        stored_at: SystemTime::now(),
+                    });
+                })),
+            };
+
+            let body = match len {
+                Some(len) => Body::from_reader_sized(reader, len),
+                None => Body::from_reader(reader),
+            };
+
+            Response::from_parts(parts, body)
+        })).boxed()
+    }
+}
+
+impl fmt::Debug for IdempotencyMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdempotencyMiddleware")
+            .field("header", &self.header)
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+/// Hands the response to an original request over to any repeated requests waiting for it.
+///
+/// If dropped without publishing a response, such as when the request fails, the waiting requests are released to be
+/// sent on their own.
+struct Publisher {
+    key: EntryKey,
+    window: Duration,
+    entries: Arc<Mutex<HashMap<EntryKey, Entry>>>,
+    sender: Option<oneshot::Sender<Arc<CachedResponse>>>,
+}
+
+impl Publisher {
+    /// Note that the response has been received, so that further repeats are sent instead of waiting for its body.
+    fn receiving(&self) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&self.key) {
+            *entry = Entry::Receiving;
+        }
+    }
+
+    fn publish(mut self, response: CachedResponse) {
+        let response = Arc::new(response);
+
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(response.clone());
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if self.window > Duration::from_secs(0) {
+            entries.insert(self.key.clone(), Entry::Complete(response, Instant::now() + self.window));
+        } else {
+            entries.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        if self.sender.is_some() {
+            self.entries.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+/// Generate a random version 4 UUID to use as a key.
+fn generate_key() -> HeaderValue {
+    let high = internal::random_u64();
+    let low = internal::random_u64();
+
+    let key = format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0x0fff,
+        (low >> 48) & 0x3fff | 0x8000,
+        low & 0xffff_ffff_ffff,
+    );
+
+    HeaderValue::from_str(&key).unwrap()
+}
@@ -0,0 +1,108 @@
+//! Middleware for limiting the rate at which requests are sent.
+
+use crate::{Error, Request, Response};
+use crate::internal;
+use crate::middleware::{Middleware, Next};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use log::*;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The lowest supported rate, in requests per second.
+const MIN_RATE: f64 = 1.0 / 3600.0;
+
+/// A middleware that limits how many requests per second a client sends, using a token bucket.
+///
+/// Each request consumes one token from the bucket, which is refilled continuously at the configured rate up to the
+/// burst size. Requests that arrive when the bucket is empty wait asynchronously for a token to become available
+/// instead of blocking the client's event loop, and are sent in the order they arrived.
+///
+/// ```rust
+/// use chttp::Client;
+/// use chttp::middleware::RateLimitMiddleware;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// // Send at most 5 requests per second, with bursts of up to 10 requests.
+/// let client = Client::builder()
+///     .with_middleware(RateLimitMiddleware::new(5.0).burst(10))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RateLimitMiddleware {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Number of tokens currently available. Negative if requests are waiting for tokens that are not available yet.
+    tokens: f64,
+    /// Time when the bucket was last refilled.
+    updated: Instant,
+}
+
+impl RateLimitMiddleware {
+    /// Create a new rate limiting middleware that allows the given number of requests per second.
+    ///
+    /// The burst size defaults to the number of requests per second, but at least one. Rates below one request per
+    /// hour, including zero, negative and NaN rates, are raised to one request per hour.
+    pub fn new(requests_per_second: f64) -> Self {
+        // `max` also replaces NaN with the minimum.
+        let requests_per_second = requests_per_second.max(MIN_RATE);
+        let burst = requests_per_second.max(1.0);
+
+        Self {
+            rate: requests_per_second,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Set the maximum number of requests that may be sent at once after the client has been idle.
+    pub fn burst(mut self, size: u32) -> Self {
+        self.burst = f64::from(size.max(1));
+        self.bucket.get_mut().unwrap().tokens = self.burst;
+        self
+    }
+
+    /// Take a token from the bucket, returning how long to wait until the token is actually available.
+    fn reserve(&self) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst) - 1.0;
+        bucket.updated = now;
+
+        if bucket.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_nanos((-bucket.tokens / self.rate * 1e9) as u64)
+        }
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn send(&self, request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        let wait = self.reserve();
+
+        if wait == Duration::from_secs(0) {
+            return next.send(request);
+        }
+
+        debug!("rate limit reached, delaying request to {} by {:?}", request.uri(), wait);
+
+        async move {
+            internal::delay(wait).await;
+            next.send(request).await
+        }.boxed()
+    }
+}
@@ -0,0 +1,215 @@
+//! Middleware for automatically retrying failed requests.
+
+use crate::{Error, Request, Response};
+use crate::internal;
+use crate::middleware::{Middleware, Next};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use http::StatusCode;
+use log::*;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A middleware that automatically retries requests that fail with a transient error.
+///
+/// By default, requests are retried if they fail to connect or time out, or if the server responds with a
+/// `429 Too Many Requests`, `502 Bad Gateway`, `503 Service Unavailable` or `504 Gateway Timeout` status. Use
+/// [`retry_if`](#method.retry_if) to customize which outcomes are retried.
+///
+/// The delay between attempts doubles after each attempt, starting from the base delay. If the server includes a
+/// `Retry-After` header with a number of seconds in a `429` or `503` response, that delay is used instead.
+///
+/// Only requests whose body can be sent more than once are retried. Requests with a streaming body are sent once, and
+/// any error is returned as-is. Requests with a method that is not idempotent, such as `POST`, are never retried after
+/// a timeout, since the server may have already acted on them.
+///
+/// ```rust
+/// use chttp::Client;
+/// use chttp::middleware::RetryMiddleware;
+/// use std::time::Duration;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let client = Client::builder()
+///     .with_middleware(RetryMiddleware::new(3)
+///         .base_delay(Duration::from_millis(200))
+///         .jitter(true))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryMiddleware {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    exponential_backoff: bool,
+    jitter: bool,
+    predicate: Arc<dyn Fn(&Result<Response, Error>) -> bool + Send + Sync>,
+}
+
+impl RetryMiddleware {
+    /// Create a new retry middleware that makes at most the given number of attempts for each request, including the
+    /// first one.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            exponential_backoff: true,
+            jitter: false,
+            predicate: Arc::new(is_retryable),
+        }
+    }
+
+    /// Set the delay before the first retry.
+    ///
+    /// The default is 100 milliseconds.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Set the maximum delay between any two attempts, including delays requested by the server.
+    ///
+    /// The default is 30 seconds.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Enable or disable doubling the delay after each attempt. When disabled, the base delay is used between all
+    /// attempts.
+    ///
+    /// The default is enabled.
+    pub fn exponential_backoff(mut self, enable: bool) -> Self {
+        self.exponential_backoff = enable;
+        self
+    }
+
+    /// Enable or disable randomizing each delay to between half and all of its value, which helps to avoid many
+    /// clients retrying at the same moment.
+    ///
+    /// The default is disabled.
+    pub fn jitter(mut self, enable: bool) -> Self {
+        self.jitter = enable;
+        self
+    }
+
+    /// Set a function that decides whether the outcome of an attempt should be retried.
+    pub fn retry_if(mut self, predicate: impl Fn(&Result<Response, Error>) -> bool + Send + Sync + 'static) -> Self {
+        self.predicate = Arc::new(predicate);
+        self
+    }
+
+    /// Send a request, retrying it as many times as allowed if it fails.
+    fn send_attempt(self, request: Request, next: Next, attempt: u32) -> BoxFuture<'static, Result<Response, Error>> {
+        // Keep a copy of the request around in case we need to send it again.
+        let retry_request = if attempt + 1 < self.max_attempts {
+            internal::try_clone_request(&request)
+        } else {
+            None
+        };
+        let idempotent = is_idempotent(request.method());
+
+        async move {
+            let result = next.send(request).await;
+            let timed_out = result.as_ref().err().map_or(false, Error::is_timeout);
+
+            match retry_request {
+                Some(retry_request) if (self.predicate)(&result) && (idempotent || !timed_out) => {
+                    let delay = self.delay(attempt, &result);
+                    debug!("retrying request to {} in {:?} (attempt {} of {})", retry_request.uri(), delay, attempt + 2, self.max_attempts);
+
+                    internal::delay(delay).await;
+                    self.send_attempt(retry_request, next, attempt + 1).await
+                },
+                _ => result,
+            }
+        }.boxed()
+    }
+
+    /// Get the delay to wait before retrying after the given attempt.
+    fn delay(&self, attempt: u32, result: &Result<Response, Error>) -> Duration {
+        let delay = match result.as_ref().ok().and_then(retry_after) {
+            Some(delay) => delay,
+            None => {
+                let mut delay = if self.exponential_backoff {
+                    self.base_delay.checked_mul(2u32.saturating_pow(attempt)).unwrap_or(self.max_delay)
+                } else {
+                    self.base_delay
+                };
+
+                if self.jitter {
+                    delay = delay / 2 + delay / 2000 * (internal::random_u64() % 1000) as u32;
+                }
+
+                delay
+            },
+        };
+
+        delay.min(self.max_delay)
+    }
+}
+
+impl Middleware for RetryMiddleware {
+    fn send(&self, request: Request, next: Next) -> BoxFuture<'static, Result<Response, Error>> {
+        self.clone().send_attempt(request, next, 0)
+    }
+}
+
+impl fmt::Debug for RetryMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryMiddleware")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("exponential_backoff", &self.exponential_backoff)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+/// The default retry predicate.
+fn is_retryable(result: &Result<Response, Error>) -> bool {
+    match result {
+        Ok(response) => match response.status() {
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT => true,
+            _ => false,
+        },
+        Err(e) => e.is_connect() || e.is_timeout(),
+    }
+}
+
+/// Check if sending a request with the given method more than once has the same effect as sending it once.
+fn is_idempotent(method: &http::Method) -> bool {
+    match *method {
+        http::Method::GET
+            | http::Method::HEAD
+            | http::Method::PUT
+            | http::Method::DELETE
+            | http::Method::OPTIONS
+            | http::Method::TRACE => true,
+        _ => false,
+    }
+}
+
+/// Get the delay requested by the server in a `Retry-After` header, if any.
+fn retry_after(response: &Response) -> Option<Duration> {
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            response.headers()
+                .get(http::header::RETRY_AFTER)?
+                .to_str()
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+                .map(Duration::from_secs)
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,152 @@
+//! Canned responses for testing code that uses a client, without sending anything over the network.
+//!
+//! A [`Mock`](struct.Mock.html) holds a list of responses to return for certain requests, and records every request
+//! that is sent to it. Build a client on top of it with
+//! [`ClientBuilder::mock`](../client/struct.ClientBuilder.html#method.mock) and hand that client to the code under test:
+//!
+//! ```rust
+//! use chttp::Client;
+//! use chttp::http::Method;
+//! use chttp::mock::Mock;
+//!
+//! # fn run() -> Result<(), chttp::Error> {
+//! let mock = Mock::new();
+//! mock.respond(Method::GET, "http://example.org/greeting", 200, "hello");
+//!
+//! let client = Client::builder().mock(mock.clone()).build()?;
+//! let mut response = client.get("http://example.org/greeting")?;
+//!
+//! assert_eq!(response.body_mut().text()?, "hello");
+//! assert_eq!(mock.requests().len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Body, Error, Request, Response};
+use crate::middleware::Transport;
+use futures::prelude::*;
+use http::{Method, StatusCode, Uri};
+use log::warn;
+use std::fmt;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+/// A request received by a mock, with its body read into memory.
+pub type MockRequest = http::Request<Vec<u8>>;
+
+/// A function that creates a response for a request received by a mock.
+type Responder = Arc<dyn Fn(&MockRequest) -> Response + Send + Sync>;
+
+/// A set of canned responses to use in place of a real server.
+///
+/// Mocks are shared handles, so a clone can be kept for checking which requests were received after the mock has been
+/// handed to a client.
+#[derive(Clone, Default)]
+pub struct Mock {
+    inner: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<(Method, Uri, Responder)>,
+    requests: Vec<MockRequest>,
+}
+
+impl Mock {
+    /// Create a new mock without any responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Respond to requests with the given method and URI with a fixed status and body.
+    ///
+    /// If more than one response matches a request, the one registered last is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the URI or the status code is invalid.
+    pub fn respond(&self, method: Method, uri: &str, status: u16, body: impl Into<Vec<u8>>) -> &Self {
+        let status = StatusCode::from_u16(status).expect("invalid status code");
+        let body = body.into();
+
+        self.respond_with(method, uri, move |_| {
+            let mut response = http::Response::new(Body::from(body.clone()));
+            *response.status_mut() = status;
+            response
+        })
+    }
+
+    /// Respond to requests with the given method and URI using a function, which can inspect the request and build
+    /// a response with custom headers.
+    ///
+    /// If more than one response matches a request, the one registered last is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the URI is invalid.
+    pub fn respond_with(
+        &self,
+        method: Method,
+        uri: &str,
+        responder: impl Fn(&MockRequest) -> Response + Send + Sync + 'static,
+    ) -> &Self {
+        let uri = uri.parse().expect("invalid URI");
+        self.inner.lock().unwrap().rules.push((method, uri, Arc::new(responder)));
+        self
+    }
+
+    /// Get a copy of all of the requests received so far, in the order they were sent.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.inner.lock().unwrap().requests.iter().map(copy_request).collect()
+    }
+
+    /// Create a transport that answers requests using this mock.
+    pub(crate) fn transport(&self) -> Transport {
+        let mock = self.clone();
+
+        Arc::new(move |request| future::ready(mock.handle(request)).boxed())
+    }
+
+    fn handle(&self, request: Request) -> Result<Response, Error> {
+        let (parts, mut body) = request.into_parts();
+        let mut bytes = Vec::new();
+        body.read_to_end(&mut bytes)?;
+        let request = http::Request::from_parts(parts, bytes);
+
+        let responder = self.inner.lock().unwrap().rules.iter()
+            .rev()
+            .find(|(method, uri, _)| method == request.method() && uri == request.uri())
+            .map(|(_, _, responder)| responder.clone());
+
+        // The lock is not held while responding, so that responders can use the mock themselves, and a panicking
+        // responder does not poison it.
+        let response = responder.map(|responder| responder(&request));
+        self.inner.lock().unwrap().requests.push(request);
+
+        response.ok_or_else(|| {
+            warn!("no mock response registered for request");
+            Error::NoResponse
+        })
+    }
+}
+
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.lock().unwrap();
+
+        f.debug_struct("Mock")
+            .field("responses", &state.rules.len())
+            .field("requests", &state.requests.len())
+            .finish()
+    }
+}
+
+/// Copy the parts of a request that can be copied. Extensions are left out.
+fn copy_request(request: &MockRequest) -> MockRequest {
+    let mut copy = http::Request::new(request.body().clone());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy
+}
@@ -1,8 +1,13 @@
 //! Definition of all configurable client options.
 
-use std::net::SocketAddr;
+use crate::Request;
+use http::{HeaderMap, Uri};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 /// Defines various protocol and connection options.
 #[derive(Clone, Debug, withers_derive::Withers)]
@@ -12,43 +17,415 @@ pub struct Options {
     /// The default is to not follow redirects.
     pub redirect_policy: RedirectPolicy,
 
+    /// Which redirects of a `POST` request keep the `POST` method and
+    /// resend the request body, when redirects are followed.
+    ///
+    /// Redirects with a status of `301`, `302` or `303` that are not kept
+    /// switch to a `GET` request without a body, like web browsers do.
+    /// Redirects with a status of `307` or `308` always keep the method and
+    /// body. Resending the body requires a body that can be sent more than
+    /// once, such as one stored in memory.
+    ///
+    /// The default is to switch to `GET` for all of `301`, `302` and `303`.
+    pub post_redirect: PostRedirectPolicy,
+
     /// A preferred HTTP version the client should attempt to use to communicate
     /// to the server with.
     ///
-    /// This is treated as a suggestion. A different version may be used if the
-    /// server does not support it or negotiates a different version.
+    /// How strictly this is applied depends on the version:
+    ///
+    /// - `HTTP_2` is a preference. HTTP/2 is negotiated if the server supports
+    ///   it, otherwise the client falls back to HTTP/1.1.
+    /// - `HTTP_11` and `HTTP_10` are hard limits. The client never uses a newer
+    ///   version than the one given, which is useful for talking to servers
+    ///   that do not handle newer versions correctly.
+    ///
+    /// Since HTTP/1.0 does not support chunked transfer encoding, forcing
+    /// HTTP/1.0 causes request bodies of unknown length to be read into memory
+    /// before the request is sent, so that a `Content-Length` header can be
+    /// sent instead.
     ///
     /// The default value is `None` (any version).
     pub preferred_http_version: Option<http::Version>,
 
+    /// Use HTTP/2 right away without first asking the server to upgrade, when
+    /// it is known in advance that the server supports cleartext HTTP/2 (also
+    /// known as h2c).
+    ///
+    /// This only applies to `http://` URLs. For `https://` URLs the HTTP
+    /// version is negotiated during the TLS handshake using ALPN, so prior
+    /// knowledge is not needed. When enabled, this takes precedence over
+    /// `preferred_http_version` for cleartext requests.
+    ///
+    /// The default value is `false`.
+    pub http2_prior_knowledge: bool,
+
     /// A timeout for the maximum time allowed for a request-response cycle.
     ///
+    /// This covers the entire request, including connecting to the server and
+    /// receiving the whole response body. To limit only the time spent
+    /// connecting, use [`connect_timeout`](#structfield.connect_timeout)
+    /// instead.
+    ///
     /// The default value is `None` (unlimited).
     pub timeout: Option<Duration>,
 
+    /// A timeout for stalled transfers, given as a minimum transfer speed in
+    /// bytes per second and a duration.
+    ///
+    /// If the transfer speed stays below the given speed for longer than the
+    /// given duration, the request is aborted with a timeout error. Unlike
+    /// [`timeout`](#structfield.timeout), this allows large transfers to take
+    /// as long as they need, as long as they keep making progress. Both
+    /// timeouts may be used together.
+    ///
+    /// The default value is `None` (disabled).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// # use std::time::Duration;
+    /// // Abort if less than 1 KiB/s is transferred for 30 seconds.
+    /// let options = Options::default()
+    ///     .with_low_speed_timeout(Some((1024, Duration::from_secs(30))));
+    /// ```
+    pub low_speed_timeout: Option<(u32, Duration)>,
+
     /// A timeout for the initial connection phase.
     ///
+    /// This only limits the time spent resolving the host name and
+    /// establishing a connection, including any TLS handshake. Once connected,
+    /// the transfer itself is only limited by
+    /// [`timeout`](#structfield.timeout), so the two can be set independently.
+    /// For example, a short connect timeout can be combined with a long or no
+    /// total timeout so that slow downloads are not interrupted. If `None`,
+    /// curl's built-in connect timeout applies, which is also 300 seconds.
+    ///
     /// The default value is 300 seconds.
-    pub connect_timeout: Duration,
+    pub connect_timeout: Option<Duration>,
+
+    /// How long to wait for a `100 Continue` response from the server before
+    /// sending the request body anyway.
+    ///
+    /// For large request bodies, curl sends an `Expect: 100-continue` header
+    /// and waits for the server to accept the request before uploading the
+    /// body. Setting this to `None` disables the `Expect` header entirely,
+    /// which avoids the extra wait with servers that do not support it. Any
+    /// `Expect` header set on the request itself is ignored in favor of this
+    /// option.
+    ///
+    /// The default value is 1 second.
+    pub expect_continue: Option<Duration>,
 
     /// Enable or disable TCP keepalive with a given probe interval.
     ///
+    /// When enabled, the given duration is used both as the time a connection
+    /// must be idle before the first keepalive probe is sent, and as the
+    /// interval between subsequent probes. This helps keep long-lived idle
+    /// connections from being silently dropped by NATs and firewalls.
+    ///
     /// The default value is `None` (disabled).
     pub tcp_keepalive: Option<Duration>,
 
     /// Enable or disable the `TCP_NODELAY` option.
     ///
+    /// Setting this to `true` disables Nagle's algorithm, which sends small
+    /// packets immediately at the cost of some extra network overhead.
+    ///
     /// The default value is `false`.
     pub tcp_nodelay: bool,
 
+    /// Enable or disable TCP Fast Open, which allows data to be sent during
+    /// the TCP handshake when reconnecting to a server that supports it.
+    ///
+    /// Support depends on the operating system. If it is not available, this
+    /// option is ignored and connections are made as usual.
+    ///
+    /// The default value is `false`.
+    pub tcp_fastopen: bool,
+
+    /// The IP version to use when connecting to hosts that resolve to both
+    /// IPv4 and IPv6 addresses.
+    ///
+    /// The default value is `IpVersion::Any`.
+    pub ip_version: IpVersion,
+
+    /// How long to wait for a connection attempt over IPv6 before also
+    /// trying IPv4 in parallel, when a host has addresses of both versions.
+    ///
+    /// Lowering this helps on networks where IPv6 is advertised but does not
+    /// work. If libcurl is too old to support this, it is ignored.
+    ///
+    /// The default value is `None`, which uses the libcurl default of 200
+    /// milliseconds.
+    pub happy_eyeballs_timeout: Option<Duration>,
+
     /// Set the max buffer size in bytes to use for reading the response body.
     ///
+    /// Larger buffers can improve throughput of large downloads from fast
+    /// servers. Libcurl accepts sizes between 1 KiB and 10 MiB; sizes outside
+    /// of this range are clamped to it, and a warning is logged.
+    ///
     /// The default value is 8 KiB.
     pub buffer_size: usize,
 
+    /// Set the buffer size in bytes to use for sending the request body.
+    ///
+    /// Libcurl accepts sizes between 16 KiB and 2 MiB; sizes outside of this
+    /// range are clamped to it, and a warning is logged.
+    ///
+    /// The default value is `None`, which uses the libcurl default of 64 KiB.
+    pub upload_buffer_size: Option<usize>,
+
+    /// The maximum allowed size of a response body, in bytes.
+    ///
+    /// If the server advertises a larger `Content-Length`, the request fails
+    /// up front. Since servers may not advertise the length or may send more
+    /// than advertised, the actual number of bytes received is checked as
+    /// well, and the transfer is aborted as soon as the limit is exceeded.
+    /// Either way, the request or reading the response body fails with
+    /// [`Error::ResponseTooLarge`](../error/enum.Error.html#variant.ResponseTooLarge).
+    /// If automatic decompression is enabled, the limit applies to the
+    /// decompressed body.
+    ///
+    /// The default value is `None` (unlimited).
+    pub max_response_size: Option<u64>,
+
+    /// Request only part of the response body, as a range of byte offsets.
+    ///
+    /// The first value is the offset of the first byte to fetch, and the
+    /// second is the offset of the last byte to fetch, inclusive. If no end
+    /// is given, everything from the start offset to the end of the resource
+    /// is fetched. A server that supports ranges responds with `206 Partial
+    /// Content`, but servers are free to ignore the range and send the whole
+    /// body with `200 OK` instead. Use [`with_range`](#method.with_range) to
+    /// set this more conveniently.
+    ///
+    /// The default value is `None` (the whole body).
+    pub byte_range: Option<(u64, Option<u64>)>,
+
+    /// Enable or disable automatic decompression of the response body.
+    ///
+    /// When enabled, an `Accept-Encoding` header is sent listing all encodings
+    /// supported by libcurl (such as `gzip` and `deflate`), and the response
+    /// body is decoded on the fly as it is read. Since the body is returned
+    /// decoded, the `Content-Encoding` and `Content-Length` headers are removed
+    /// from the response.
+    ///
+    /// The default value is `true`.
+    pub automatic_decompression: bool,
+
+    /// The value of the `Accept-Encoding` header to send when automatic
+    /// decompression is enabled.
+    ///
+    /// An empty string lists all encodings supported by libcurl, while a
+    /// specific value such as `"br"` is sent as-is. Responses are only decoded
+    /// if libcurl supports their encoding. If set to `None`, no header is sent
+    /// and responses are not decoded, the same as disabling
+    /// [`automatic_decompression`](#structfield.automatic_decompression).
+    /// When automatic decompression is disabled, this option is ignored; set
+    /// an `Accept-Encoding` header on the request yourself to negotiate an
+    /// encoding and receive the body as sent by the server.
+    ///
+    /// The default value is `Some("")` (all supported encodings).
+    pub accept_encoding: Option<String>,
+
+    /// Enable or disable collecting timing metrics for requests.
+    ///
+    /// When enabled, a [`Metrics`](../metrics/struct.Metrics.html) object is
+    /// attached to each response as an extension. The timings are reported by
+    /// curl itself, so enabling this has negligible overhead.
+    ///
+    /// The default value is `false`.
+    pub metrics: bool,
+
+    /// A function to call periodically with the progress of the request body
+    /// upload and response body download.
+    ///
+    /// The handler is called from the client's event loop thread, which is
+    /// shared with all other requests made by the same client, so it should
+    /// return quickly and must not block.
+    ///
+    /// The default value is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_progress(Some(ProgressHandler::new(|progress| {
+    ///         if let Some(total) = progress.total_download {
+    ///             println!("downloaded {} of {} bytes", progress.bytes_received, total);
+    ///         }
+    ///     })));
+    /// ```
+    pub progress: Option<ProgressHandler>,
+
+    /// A function that produces trailing headers to send after the request
+    /// body.
+    ///
+    /// The function is called once the whole request body has been sent.
+    /// Trailers can only be sent with HTTP/2 requests and HTTP/1.1 requests
+    /// with a chunked body, which is used for bodies of unknown length. For
+    /// other requests, this option has no effect. Servers may expect the
+    /// names of the trailers to be announced in a `Trailer` request header.
+    ///
+    /// The default value is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::http::HeaderMap;
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_trailers(Some(TrailerHandler::new(|| {
+    ///         let mut trailers = HeaderMap::new();
+    ///         trailers.insert("grpc-status", "0".parse().unwrap());
+    ///         trailers
+    ///     })));
+    /// ```
+    pub trailers: Option<TrailerHandler>,
+
+    /// A function that is called with each new socket after it is created,
+    /// but before it is connected.
+    ///
+    /// This gives access to the raw socket for setting options that are not
+    /// otherwise exposed, such as `SO_MARK` on Linux. The socket is passed as
+    /// a [`RawSocket`](type.RawSocket.html), which is a file descriptor on
+    /// Unix and a `SOCKET` handle on Windows. Returning an error aborts the
+    /// connection, and the request fails.
+    ///
+    /// The function is only called for new connections, not when an existing
+    /// connection is reused.
+    ///
+    /// The default value is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_socket_callback(Some(SocketHandler::new(|socket| {
+    ///         println!("created socket {:?}", socket);
+    ///         Ok(())
+    ///     })));
+    /// ```
+    pub socket_callback: Option<SocketHandler>,
+
+    /// The maximum number of bytes of each request and response body to
+    /// include in wire logs.
+    ///
+    /// The chunk of body data that reaches this limit is logged to the
+    /// `chttp::wire` target with a `...(truncated, N bytes in chunk)` marker,
+    /// where `N` is the length of that chunk rather than of the whole body,
+    /// and the rest of the body is not logged. Headers are always logged in
+    /// full.
+    ///
+    /// The default value is `None`, which logs body data in full.
+    pub wire_log_body_limit: Option<usize>,
+
+    /// Enable or disable logging of libcurl's own diagnostic messages, such
+    /// as whether a connection is reused and details of the TLS handshake.
+    ///
+    /// When enabled, the messages are logged at the `Debug` level to the
+    /// `chttp::curl` target. Otherwise, they are only logged at the `Trace`
+    /// level.
+    ///
+    /// The default value is `false`.
+    pub verbose: bool,
+
     /// Indicates whether the `Referer` header should be automatically updated.
+    ///
+    /// When enabled, each request made to follow a redirect has its
+    /// `Referer` header set to the URL that issued the redirect. This only
+    /// has an effect when redirects are followed according to
+    /// [`redirect_policy`](#structfield.redirect_policy).
+    ///
+    /// The default value is `false`.
     pub auto_referer: bool,
 
+    /// Cookies to send with the request, in the same `name=value; name2=value2`
+    /// format as a `Cookie` header.
+    ///
+    /// This is useful for sending cookies obtained elsewhere without setting
+    /// up a cookie jar. The cookies are sent in addition to any cookies
+    /// already in the request, such as those added by a cookie jar, and come
+    /// after them in the `Cookie` header. Since servers generally use the
+    /// first cookie with a given name, cookies from a jar take precedence over
+    /// cookies given here with the same name.
+    ///
+    /// The default value is `None`.
+    pub cookies: Option<String>,
+
+    /// Send the path of the request URI exactly as given.
+    ///
+    /// By default, `.` and `..` segments in the path are resolved before
+    /// the request is sent, so a request to `https://example.org/a/../b` is
+    /// sent for the path `/b`. Enable this option for servers that expect
+    /// such segments literally.
+    ///
+    /// The default value is `false`.
+    pub path_as_is: bool,
+
+    /// Only fetch the resource if it has been modified since the given time,
+    /// by sending an `If-Modified-Since` header.
+    ///
+    /// If the resource has not been modified, the server responds with a
+    /// `304 Not Modified` status and no body, which is returned like any
+    /// other response. This option has no effect on requests that already
+    /// include an `If-Modified-Since` header.
+    ///
+    /// The default value is `None`.
+    pub if_modified_since: Option<SystemTime>,
+
+    /// Only fetch the resource if its entity tag does not match the given
+    /// value, by sending an `If-None-Match` header.
+    ///
+    /// The value is sent as given, so it should be a quoted entity tag as
+    /// returned in an `ETag` response header, a comma-separated list of
+    /// them, or `*`. If the tag matches, the server responds with a
+    /// `304 Not Modified` status and no body, which is returned like any
+    /// other response. This option has no effect on requests that already
+    /// include an `If-None-Match` header.
+    ///
+    /// The default value is `None`.
+    pub if_none_match: Option<String>,
+
+    /// Treat responses with a `4xx` or `5xx` status code as errors.
+    ///
+    /// When enabled, sending a request returns
+    /// [`Error::Status`](../error/enum.Error.html#variant.Status) instead of
+    /// such a response, similar to `curl --fail`. The error includes the
+    /// headers of the response and up to 64 KiB of its body. Middleware still
+    /// sees the original response.
+    ///
+    /// The default value is `false`.
+    pub fail_on_error: bool,
+
+    /// Credentials to use to authenticate with the server.
+    ///
+    /// If the request already includes an `Authorization` header, bearer
+    /// tokens will not override it.
+    ///
+    /// The default value is `None`.
+    pub authentication: Option<Authentication>,
+
+    /// Whether to look up credentials for the server in a `.netrc` file.
+    ///
+    /// Credentials found in the file are used unless
+    /// [`authentication`](#structfield.authentication) is set, which always
+    /// takes precedence.
+    ///
+    /// The default value is `NetrcPolicy::Ignore`.
+    pub netrc: NetrcPolicy,
+
+    /// The `.netrc` file to read credentials from.
+    ///
+    /// The default value is `None`, which reads `.netrc` in the home
+    /// directory of the current user.
+    pub netrc_file: Option<PathBuf>,
+
     /// A proxy to use for requests.
     ///
     /// The proxy protocol is specified by the URI scheme.
@@ -61,24 +438,138 @@ pub struct Options {
     /// - **`socks5h`**: SOCKS5 Proxy. Proxy resolves URL hostname.
     pub proxy: Option<http::Uri>,
 
+    /// A list of hosts that should be connected to directly instead of
+    /// through the configured [`proxy`](#structfield.proxy).
+    ///
+    /// Each entry is a host name, domain, or IP address. A domain also matches
+    /// all of its subdomains, so `example.com` matches both `example.com` and
+    /// `www.example.com`; a leading `*.` wildcard is accepted and has the same
+    /// effect. A single `*` entry disables the proxy for all hosts.
+    ///
+    /// The default value is empty.
+    pub proxy_blacklist: Vec<String>,
+
+    /// Credentials to use to authenticate with the proxy.
+    ///
+    /// Only used if a [`proxy`](#structfield.proxy) is set. Bearer tokens are
+    /// only sent to the proxy, never to the server at the other end of a
+    /// tunnel. If the request already includes a `Proxy-Authorization` header,
+    /// bearer tokens will not override it.
+    ///
+    /// The default value is `None`.
+    pub proxy_authentication: Option<Authentication>,
+
+    /// Always tunnel requests through the configured
+    /// [`proxy`](#structfield.proxy) using `CONNECT`.
+    ///
+    /// Requests to `https` URIs are always tunneled. When enabled, requests to
+    /// `http` URIs are tunneled too, instead of being sent to the proxy with
+    /// the absolute URI as the request target. This is required for proxies
+    /// that only support `CONNECT`, and keeps the proxy from seeing the
+    /// contents of plain HTTP requests.
+    ///
+    /// The default value is `false`.
+    pub proxy_tunnel: bool,
+
+    /// A path to a Unix domain socket to connect to instead of connecting over
+    /// TCP.
+    ///
+    /// The request URI is still used to determine the `Host` header and the
+    /// request path, but the connection itself is made to the given socket.
+    /// Connections are only reused for requests using the same socket path.
+    /// Sending a request fails if the path is not valid UTF-8.
+    ///
+    /// The default value is `None` (connect over TCP).
+    pub unix_socket: Option<PathBuf>,
+
+    /// A network interface or source address to make outgoing connections
+    /// from.
+    ///
+    /// The value may be an interface name, an IP address, or a host name.
+    /// Prefix the value with `if!` to force it to be treated as an interface
+    /// name, or with `host!` to force it to be treated as an IP address or
+    /// host name. Pooled connections are only reused for requests using the
+    /// same interface.
+    ///
+    /// The default value is `None` (chosen by the operating system).
+    pub interface: Option<String>,
+
+    /// A range of local ports to make outgoing connections from, given as the
+    /// first port and the number of ports in the range.
+    ///
+    /// This is useful when a firewall only allows outgoing connections from
+    /// certain ports. If none of the ports in the range are available, the
+    /// request fails with
+    /// [`Error::BindFailed`](../error/enum.Error.html#variant.BindFailed)
+    /// instead of using a different port. Use
+    /// [`with_local_port_range`](#method.with_local_port_range) to set this
+    /// more conveniently.
+    ///
+    /// The default value is `None` (chosen by the operating system).
+    pub local_ports: Option<(u16, u16)>,
+
     /// A list of specific DNS servers to be used for DNS resolution.
     ///
     /// By default this option is not set and the system's built-in DNS
     /// resolver is used. This option can only be used if libcurl is compiled
-    /// with [c-ares](https://c-ares.haxx.se), otherwise this option has no
-    /// effect.
+    /// with [c-ares](https://c-ares.haxx.se), otherwise sending a request
+    /// with this option set will fail with an error.
     pub dns_servers: Option<Vec<SocketAddr>>,
 
+    /// A list of static host name to IP address mappings to use instead of
+    /// resolving the given hosts using DNS.
+    ///
+    /// Each entry is a host name, a port, and the address that connections to
+    /// that host and port should be made to. The original host name is still
+    /// used for the `Host` header and for TLS server name indication and
+    /// certificate validation. Entries can be added one at a time using
+    /// [`with_dns_override`](#method.with_dns_override).
+    ///
+    /// The default value is empty.
+    pub dns_overrides: Vec<(String, u16, IpAddr)>,
+
+    /// A list of rules for connecting to a different host and port than the
+    /// one in the request URI.
+    ///
+    /// Unlike [`dns_overrides`](#structfield.dns_overrides), the connection
+    /// can be redirected to a different port, and the target is a host name
+    /// rather than an address. The original host name is still used for the
+    /// `Host` header and for TLS server name indication and certificate
+    /// validation.
+    ///
+    /// The default value is empty.
+    pub connect_to: Vec<ConnectTo>,
+
     /// A maximum upload speed for the request body, in bytes per second.
     ///
+    /// This is useful for keeping background transfers from saturating a
+    /// shared connection. The transfer is paused as needed to stay below the
+    /// limit on average. A limit of zero is the same as no limit.
+    ///
     /// The default is unlimited.
     pub max_upload_speed: Option<u64>,
 
     /// A maximum download speed for the response body, in bytes per second.
     ///
+    /// The transfer is paused as needed to stay below the limit on average. A
+    /// limit of zero is the same as no limit.
+    ///
     /// The default is unlimited.
     pub max_download_speed: Option<u64>,
 
+    /// The range of TLS versions that may be used for SSL/TLS connections,
+    /// given as a minimum version and an optional maximum version.
+    ///
+    /// If the server does not support any version in the range, the request
+    /// fails with
+    /// [`Error::SSLConnectFailed`](../error/enum.Error.html#variant.SSLConnectFailed),
+    /// which describes the negotiation failure. Use
+    /// [`with_tls_versions`](#method.with_tls_versions) to set this more
+    /// conveniently.
+    ///
+    /// The default value is `None` (use the defaults of the SSL/TLS engine).
+    pub tls_version_range: Option<(TlsVersion, Option<TlsVersion>)>,
+
     /// A list of ciphers to use for SSL/TLS connections.
     ///
     /// The list of valid cipher names is dependent on the underlying SSL/TLS
@@ -87,14 +578,55 @@ pub struct Options {
     /// You can find an up-to-date list of potential cipher names at
     /// <https://curl.haxx.se/docs/ssl-ciphers.html>.
     ///
+    /// This list applies to TLS 1.2 and older. The cipher suites used for TLS
+    /// 1.3 are configured separately using
+    /// [`tls13_ciphers`](#structfield.tls13_ciphers). If none of the given
+    /// names are valid, the request fails with
+    /// [`Error::SSLConnectFailed`](../error/enum.Error.html#variant.SSLConnectFailed)
+    /// instead of falling back to the default ciphers.
+    ///
     /// The default is unset and will result in the system defaults being used.
     pub ssl_ciphers: Option<Vec<String>>,
 
+    /// A list of cipher suites to use for TLS 1.3 connections, such as
+    /// `TLS_AES_256_GCM_SHA384`.
+    ///
+    /// Not all SSL/TLS engines support configuring TLS 1.3 cipher suites. If
+    /// this is set and the engine in use does not support it, sending a
+    /// request fails with an error.
+    ///
+    /// The default is unset and will result in the system defaults being used.
+    pub tls13_ciphers: Option<Vec<String>>,
+
+    /// Public keys that the server's certificate must match, in addition to
+    /// the usual certificate validation.
+    ///
+    /// The value is either one or more base64-encoded SHA-256 hashes of
+    /// public keys, each prefixed with `sha256//` and separated by `;`, or the
+    /// path of a file containing a public key in PEM or DER format. Pinning
+    /// the public key protects against certificates issued by a compromised
+    /// certificate authority. If the server's key does not match, the request
+    /// fails with
+    /// [`Error::PinnedPublicKeyMismatch`](../error/enum.Error.html#variant.PinnedPublicKeyMismatch).
+    ///
+    /// The default value is `None` (no pinning).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_pinned_public_key(Some("sha256//YhKJKSzoTt2b5FP18fvpHo7fJYqQCjAa3HWY3tvRMwE=".into()));
+    /// ```
+    pub pinned_public_key: Option<String>,
+
     /// A custom SSL/TLS client certificate to use for all client connections.
     ///
     /// If a format is not supported by the underlying SSL/TLS engine, an error
     /// will be returned when attempting to send a request using the offending
-    /// certificate.
+    /// certificate. The certificate and private key files must exist when the
+    /// request is sent, otherwise the request will fail with
+    /// [`Error::BadClientCertificate`](../error/enum.Error.html#variant.BadClientCertificate).
     ///
     /// The default value is none.
     ///
@@ -113,6 +645,52 @@ pub struct Options {
     ///     .with_ssl_client_certificate(Some(cert));
     /// ```
     pub ssl_client_certificate: Option<ClientCertificate>,
+
+    /// Enable or disable verification of the server's SSL/TLS certificate and
+    /// host name.
+    ///
+    /// Disabling verification makes connections vulnerable to
+    /// man-in-the-middle attacks, and should only ever be done for local
+    /// development, such as when testing against a self-signed certificate.
+    /// A warning is logged the first time each client sends a request with
+    /// verification disabled. To trust a custom certificate authority instead,
+    /// use [`ca_certificate`](#structfield.ca_certificate).
+    ///
+    /// The default value is `true`.
+    pub ssl_verify: bool,
+
+    /// Enable or disable resuming TLS sessions from earlier connections to
+    /// the same server.
+    ///
+    /// When disabled, every new connection performs a full TLS handshake,
+    /// which shows up in the
+    /// [`tls_handshake_time`](../metrics/struct.Metrics.html#method.tls_handshake_time)
+    /// metric. Requests sent over a connection that is already open do not
+    /// perform a handshake at all.
+    ///
+    /// The default value is `true`.
+    pub ssl_session_reuse: bool,
+
+    /// A path to a file containing one or more certificate authorities to use
+    /// to verify servers, in PEM format.
+    ///
+    /// When set, the given bundle is used instead of the system's default trust
+    /// store, including any default CA directory unless `ca_path` is also set.
+    /// The file must exist when the request is sent, otherwise the
+    /// request will fail with an error.
+    ///
+    /// The default value is `None` (use the system default).
+    pub ca_certificate: Option<PathBuf>,
+
+    /// A path to a directory containing certificate authorities to use to
+    /// verify servers, in the format expected by the SSL/TLS engine.
+    ///
+    /// When set, the given directory is used instead of the system's default
+    /// trust store. The directory must exist when the request is sent,
+    /// otherwise the request will fail with an error.
+    ///
+    /// The default value is `None` (use the system default).
+    pub ca_path: Option<PathBuf>,
 }
 
 impl Default for Options {
@@ -120,25 +698,210 @@ impl Default for Options {
     fn default() -> Self {
         Self {
             redirect_policy: RedirectPolicy::default(),
+            post_redirect: PostRedirectPolicy::default(),
             preferred_http_version: None,
+            http2_prior_knowledge: false,
             timeout: None,
-            connect_timeout: Duration::from_secs(300),
+            low_speed_timeout: None,
+            connect_timeout: Some(Duration::from_secs(300)),
+            expect_continue: Some(Duration::from_secs(1)),
             tcp_keepalive: None,
             tcp_nodelay: false,
+            tcp_fastopen: false,
+            ip_version: IpVersion::Any,
+            happy_eyeballs_timeout: None,
             buffer_size: 8192,
+            upload_buffer_size: None,
+            max_response_size: None,
+            byte_range: None,
+            automatic_decompression: true,
+            accept_encoding: Some(String::new()),
+            metrics: false,
+            progress: None,
+            trailers: None,
+            socket_callback: None,
+            wire_log_body_limit: None,
+            verbose: false,
             auto_referer: false,
+            cookies: None,
+            path_as_is: false,
+            if_modified_since: None,
+            if_none_match: None,
+            fail_on_error: false,
+            authentication: None,
+            netrc: NetrcPolicy::Ignore,
+            netrc_file: None,
             proxy: None,
+            proxy_blacklist: Vec::new(),
+            proxy_authentication: None,
+            proxy_tunnel: false,
+            unix_socket: None,
+            interface: None,
+            local_ports: None,
             dns_servers: None,
+            dns_overrides: Vec::new(),
+            connect_to: Vec::new(),
             max_upload_speed: None,
             max_download_speed: None,
+            tls_version_range: None,
             ssl_ciphers: None,
+            tls13_ciphers: None,
+            pinned_public_key: None,
             ssl_client_certificate: None,
+            ssl_verify: true,
+            ssl_session_reuse: true,
+            ca_certificate: None,
+            ca_path: None,
+        }
+    }
+}
+
+impl Options {
+    /// Add a static mapping of a host name and port to an IP address, which
+    /// will be used instead of resolving the host using DNS.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_dns_override("api.example.com", 443, [127, 0, 0, 1].into());
+    /// ```
+    pub fn with_dns_override(mut self, host: &str, port: u16, addr: IpAddr) -> Self {
+        self.dns_overrides.push((host.to_owned(), port, addr));
+        self
+    }
+
+    /// Request only the bytes from `start` to `end` of the response body,
+    /// inclusive, or from `start` to the end of the body if `end` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// // Fetch the first kilobyte.
+    /// let options = Options::default().with_range(0, Some(1023));
+    /// ```
+    pub fn with_range(mut self, start: u64, end: Option<u64>) -> Self {
+        self.byte_range = Some((start, end));
+        self
+    }
+
+    /// Make outgoing connections from one of `count` local ports, starting
+    /// with the port `start`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// // Use one of the ports 40000 to 40099.
+    /// let options = Options::default().with_local_port_range(40000, 100);
+    /// ```
+    pub fn with_local_port_range(mut self, start: u16, count: u16) -> Self {
+        self.local_ports = Some((start, count));
+        self
+    }
+
+    /// Only allow TLS versions from `min` up to `max` for SSL/TLS
+    /// connections, or any version from `min` upwards if `max` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// // Require TLS 1.2 exactly.
+    /// let options = Options::default()
+    ///     .with_tls_versions(TlsVersion::Tls12, Some(TlsVersion::Tls12));
+    /// ```
+    pub fn with_tls_versions(mut self, min: TlsVersion, max: Option<TlsVersion>) -> Self {
+        self.tls_version_range = Some((min, max));
+        self
+    }
+
+    /// Restrict the allowed ciphers to those in the given list, separated by
+    /// colons, commas or spaces, like in an OpenSSL cipher list.
+    ///
+    /// TLS 1.3 cipher suites, whose names start with `TLS_`, are put in
+    /// [`tls13_ciphers`](#structfield.tls13_ciphers), and all other ciphers
+    /// are put in [`ssl_ciphers`](#structfield.ssl_ciphers).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// let options = Options::default()
+    ///     .with_ciphers("ECDHE-RSA-AES256-GCM-SHA384:TLS_AES_256_GCM_SHA384");
+    /// ```
+    pub fn with_ciphers(mut self, ciphers: impl AsRef<str>) -> Self {
+        let (tls13_ciphers, ssl_ciphers): (Vec<String>, Vec<String>) = ciphers.as_ref()
+            .split(|c| c == ':' || c == ',' || c == ' ')
+            .filter(|cipher| !cipher.is_empty())
+            .map(ToOwned::to_owned)
+            .partition(|cipher| cipher.starts_with("TLS_"));
+
+        if !ssl_ciphers.is_empty() {
+            self.ssl_ciphers = Some(ssl_ciphers);
+        }
+        if !tls13_ciphers.is_empty() {
+            self.tls13_ciphers = Some(tls13_ciphers);
+        }
+
+        self
+    }
+
+    /// Get the `Accept-Encoding` value to pass to curl, or `None` if responses
+    /// should not be decoded.
+    pub(crate) fn decompression_encoding(&self) -> Option<&str> {
+        if self.automatic_decompression {
+            self.accept_encoding.as_ref().map(String::as_str)
+        } else {
+            None
+        }
+    }
+}
+
+/// A rule for connecting to a different host and port than the one a request
+/// is addressed to.
+///
+/// # Examples
+///
+/// ```
+/// # use chttp::options::*;
+/// let options = Options::default()
+///     .with_connect_to(vec![
+///         ConnectTo::new("origin.example.com", 443, "edge.example.net", 8443),
+///     ]);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConnectTo {
+    /// The host name in request URIs this rule applies to.
+    pub host: String,
+
+    /// The port in request URIs this rule applies to.
+    pub port: u16,
+
+    /// The host to connect to instead.
+    pub connect_host: String,
+
+    /// The port to connect to instead.
+    pub connect_port: u16,
+}
+
+impl ConnectTo {
+    /// Create a rule to connect to `connect_host:connect_port` for requests
+    /// addressed to `host:port`.
+    pub fn new(host: impl Into<String>, port: u16, connect_host: impl Into<String>, connect_port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            connect_host: connect_host.into(),
+            connect_port,
         }
     }
 }
 
 /// Describes a policy for handling server redirects.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone)]
 pub enum RedirectPolicy {
     /// Do not apply any special treatment to redirect responses. The response
     /// will be returned as-is and redirects will not be followed.
@@ -149,14 +912,284 @@ pub enum RedirectPolicy {
     Follow,
     /// Follow redirects automatically up to a maximum number of redirects.
     Limit(u32),
+    /// Decide whether to follow each redirect using a custom function.
+    ///
+    /// The function is called with the request that produced the redirect
+    /// response (without its body) and the URI being redirected to. If it
+    /// returns `false`, the redirect response is returned as-is instead of
+    /// being followed. The body of a rejected redirect response is not
+    /// available.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chttp::options::*;
+    /// # use std::sync::Arc;
+    /// // Never follow redirects that downgrade from HTTPS to HTTP.
+    /// let policy = RedirectPolicy::Custom(Arc::new(|request, target| {
+    ///     request.uri().scheme_str() != Some("https") || target.scheme_str() == Some("https")
+    /// }));
+    /// ```
+    Custom(Arc<dyn Fn(&Request, &Uri) -> bool + Send + Sync>),
 }
 
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedirectPolicy::None => write!(f, "None"),
+            RedirectPolicy::Follow => write!(f, "Follow"),
+            RedirectPolicy::Limit(max) => f.debug_tuple("Limit").field(max).finish(),
+            RedirectPolicy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Custom policies are only equal if they share the same function.
+impl PartialEq for RedirectPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RedirectPolicy::None, RedirectPolicy::None) => true,
+            (RedirectPolicy::Follow, RedirectPolicy::Follow) => true,
+            (RedirectPolicy::Limit(a), RedirectPolicy::Limit(b)) => a == b,
+            (RedirectPolicy::Custom(a), RedirectPolicy::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RedirectPolicy {}
+
 impl Default for RedirectPolicy {
     fn default() -> Self {
         RedirectPolicy::None
     }
 }
 
+/// Describes which redirects of a `POST` request keep the `POST` method and request body.
+///
+/// The default policy keeps none of them, which switches to a `GET` request for all three status codes.
+///
+/// # Examples
+///
+/// ```
+/// # use chttp::options::*;
+/// // Treat 301 and 302 like 308 and 307, but still switch to GET for 303.
+/// let policy = PostRedirectPolicy {
+///     keep_on_301: true,
+///     keep_on_302: true,
+///     ..PostRedirectPolicy::default()
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PostRedirectPolicy {
+    /// Keep the method and body when redirected with `301 Moved Permanently`.
+    pub keep_on_301: bool,
+
+    /// Keep the method and body when redirected with `302 Found`.
+    pub keep_on_302: bool,
+
+    /// Keep the method and body when redirected with `303 See Other`.
+    pub keep_on_303: bool,
+}
+
+impl PostRedirectPolicy {
+    /// A policy that keeps the method and body for all redirects.
+    pub fn keep_all() -> Self {
+        Self {
+            keep_on_301: true,
+            keep_on_302: true,
+            keep_on_303: true,
+        }
+    }
+}
+
+/// A snapshot of the transfer progress of a request.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of bytes of the request body sent so far.
+    pub bytes_sent: u64,
+
+    /// Number of bytes of the response body received so far.
+    pub bytes_received: u64,
+
+    /// Total size of the request body, if known.
+    pub total_upload: Option<u64>,
+
+    /// Total size of the response body, if known.
+    pub total_download: Option<u64>,
+}
+
+/// A function that receives progress updates for a request.
+#[derive(Clone)]
+pub struct ProgressHandler(Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl ProgressHandler {
+    /// Create a new progress handler from a function.
+    pub fn new(f: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        ProgressHandler(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, progress: Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl From<Arc<dyn Fn(Progress) + Send + Sync>> for ProgressHandler {
+    fn from(f: Arc<dyn Fn(Progress) + Send + Sync>) -> Self {
+        ProgressHandler(f)
+    }
+}
+
+impl fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProgressHandler")
+    }
+}
+
+/// A function that produces trailing headers for a request.
+#[derive(Clone)]
+pub struct TrailerHandler(Arc<dyn Fn() -> HeaderMap + Send + Sync>);
+
+impl TrailerHandler {
+    /// Create a new trailer handler from a function.
+    pub fn new(f: impl Fn() -> HeaderMap + Send + Sync + 'static) -> Self {
+        TrailerHandler(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self) -> HeaderMap {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for TrailerHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TrailerHandler")
+    }
+}
+
+/// A raw operating system socket, as passed to a [`SocketHandler`](struct.SocketHandler.html).
+///
+/// This is a [`RawFd`](https://doc.rust-lang.org/std/os/unix/io/type.RawFd.html) on Unix platforms.
+#[cfg(unix)]
+pub type RawSocket = std::os::unix::io::RawFd;
+
+/// A raw operating system socket, as passed to a [`SocketHandler`](struct.SocketHandler.html).
+///
+/// This is a [`RawSocket`](https://doc.rust-lang.org/std/os/windows/io/type.RawSocket.html) on Windows.
+#[cfg(windows)]
+pub type RawSocket = std::os::windows::io::RawSocket;
+
+/// A function that configures newly created sockets.
+#[derive(Clone)]
+pub struct SocketHandler(Arc<dyn Fn(RawSocket) -> io::Result<()> + Send + Sync>);
+
+impl SocketHandler {
+    /// Create a new socket handler from a function.
+    pub fn new(f: impl Fn(RawSocket) -> io::Result<()> + Send + Sync + 'static) -> Self {
+        SocketHandler(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, socket: RawSocket) -> io::Result<()> {
+        (self.0)(socket)
+    }
+}
+
+impl From<Arc<dyn Fn(RawSocket) -> io::Result<()> + Send + Sync>> for SocketHandler {
+    fn from(f: Arc<dyn Fn(RawSocket) -> io::Result<()> + Send + Sync>) -> Self {
+        SocketHandler(f)
+    }
+}
+
+impl fmt::Debug for SocketHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SocketHandler")
+    }
+}
+
+/// Credentials for authenticating a request.
+///
+/// Credentials are never included in debug output or wire logs.
+#[derive(Clone, Eq, PartialEq)]
+pub enum Authentication {
+    /// HTTP Basic authentication with a username and password.
+    Basic {
+        /// The user name.
+        username: String,
+
+        /// The password.
+        password: String,
+    },
+    /// Bearer token authentication, such as an OAuth 2.0 access token.
+    Bearer(String),
+    /// NTLM authentication with a username and password.
+    ///
+    /// NTLM is a connection-based protocol, so it requires persistent
+    /// connections and may fail if the connection is closed between
+    /// handshake steps.
+    Ntlm {
+        /// The user name, optionally including a domain as `DOMAIN\user`.
+        username: String,
+
+        /// The password.
+        password: String,
+    },
+}
+
+impl fmt::Debug for Authentication {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Authentication::Basic {username, ..} => f.debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Authentication::Bearer(_) => f.debug_tuple("Bearer")
+                .field(&"<redacted>")
+                .finish(),
+            Authentication::Ntlm {username, ..} => f.debug_struct("Ntlm")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// Whether credentials are read from a `.netrc` file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NetrcPolicy {
+    /// Do not read credentials from a `.netrc` file.
+    Ignore,
+    /// Use credentials from a `.netrc` file if the file contains an entry for
+    /// the server. Credentials included in the URI are preferred.
+    Optional,
+    /// Always use credentials from a `.netrc` file, ignoring any credentials
+    /// included in the URI.
+    Required,
+}
+
+/// A version of the Internet Protocol to connect with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IpVersion {
+    /// Only connect using IPv4 addresses.
+    V4,
+    /// Only connect using IPv6 addresses.
+    V6,
+    /// Connect using addresses of any version.
+    Any,
+}
+
+/// A version of the TLS protocol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TlsVersion {
+    /// TLS 1.0.
+    Tls10,
+    /// TLS 1.1.
+    Tls11,
+    /// TLS 1.2.
+    Tls12,
+    /// TLS 1.3.
+    Tls13,
+}
+
 /// A public key certificate file.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ClientCertificate {
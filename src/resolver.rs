@@ -0,0 +1,71 @@
+//! Custom host name resolution.
+
+use crate::options::{ConnectTo, Options};
+use http::Uri;
+use std::net::SocketAddr;
+
+/// A custom resolver for looking up the addresses of hosts, used instead of DNS.
+///
+/// This makes it possible to find servers using service discovery, or to balance requests across several servers on
+/// the client side. A resolver is installed on a client using
+/// [`ClientBuilder::resolver`](../client/struct.ClientBuilder.html#method.resolver), and is asked for the addresses of
+/// the host of every request the client sends. Any function with the same signature as
+/// [`resolve`](#tymethod.resolve) can be used as a resolver.
+///
+/// The resolver is only asked about the host of the request as it was sent. If the request follows a redirect to a
+/// different host, the address of that host is looked up using DNS instead. To resolve every host with the resolver,
+/// disable automatic redirects and follow them yourself.
+///
+/// ```rust
+/// use chttp::Client;
+/// use std::net::SocketAddr;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let client = Client::builder()
+///     .resolver(|host: &str, port: u16| -> Vec<SocketAddr> {
+///         match host {
+///             "api.internal" => vec![SocketAddr::from(([10, 0, 0, 7], port))],
+///             _ => Vec::new(),
+///         }
+///     })
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Resolver: Send + Sync + 'static {
+    /// Get the addresses to connect to for the given host and port.
+    ///
+    /// Returning an empty list falls back to resolving the host using DNS. Connections are attempted to the returned
+    /// addresses in order. If an address has a different port than the one requested, the connection is made to that
+    /// port instead, in which case only the first address is used.
+    fn resolve(&self, host: &str, port: u16) -> Vec<SocketAddr>;
+}
+
+impl<F> Resolver for F where F: Fn(&str, u16) -> Vec<SocketAddr> + Send + Sync + 'static {
+    fn resolve(&self, host: &str, port: u16) -> Vec<SocketAddr> {
+        (self)(host, port)
+    }
+}
+
+/// Resolve the host of a request URI with a custom resolver, and add the results to the given request options.
+pub(crate) fn apply(resolver: &dyn Resolver, uri: &Uri, options: &mut Options) {
+    let host = match uri.host() {
+        Some(host) => host,
+        None => return,
+    };
+    let port = uri.port_u16().unwrap_or_else(|| match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    });
+
+    let addrs = resolver.resolve(host, port);
+
+    if addrs.iter().all(|addr| addr.port() == port) {
+        for addr in addrs {
+            options.dns_overrides.push((host.to_owned(), port, addr.ip()));
+        }
+    } else {
+        let addr = addrs[0];
+        options.connect_to.push(ConnectTo::new(host, port, addr.ip().to_string(), addr.port()));
+    }
+}
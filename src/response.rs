@@ -0,0 +1,306 @@
+//! Extensions to the standard HTTP response type.
+
+use crate::{Body, Error, Response};
+use http::Uri;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+
+/// Size of the buffer used to read chunks of a response body.
+const CHUNK_SIZE: usize = 8192;
+
+/// The URI of the last request made to produce a response. Stored as a response extension.
+#[derive(Clone, Debug)]
+pub(crate) struct EffectiveUri(pub(crate) Uri);
+
+/// The reason phrase sent by the server in the status line of a response. Stored as a response extension.
+#[derive(Clone, Debug)]
+pub(crate) struct ReasonPhrase(pub(crate) String);
+
+/// The number of redirects followed to produce a response. Stored as a response extension.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RedirectCount(pub(crate) u32);
+
+/// The addresses of both ends of the connection a response was received on. Stored as a response extension.
+///
+/// ```rust
+/// use chttp::ConnectionInfo;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let response = chttp::get("https://example.org")?;
+///
+/// if let Some(info) = response.extensions().get::<ConnectionInfo>() {
+///     println!("connected to {} from {}", info.remote_addr(), info.local_addr());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// This is only available for responses received from a server over an IP connection, and not for responses produced
+/// by middleware or a mock.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConnectionInfo {
+    pub(crate) remote_addr: SocketAddr,
+    pub(crate) local_addr: SocketAddr,
+}
+
+impl ConnectionInfo {
+    /// The address of the server, or of the proxy if the request was sent through one.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// The local address the connection was made from.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Provides extension methods for working with HTTP responses.
+///
+/// This trait is implemented for the [`Response`](../type.Response.html) type, and is re-exported at the crate root so
+/// that it can be imported easily:
+///
+/// ```rust
+/// use chttp::ResponseExt;
+///
+/// # fn run() -> Result<(), chttp::Error> {
+/// let response = chttp::get("https://example.org")?;
+/// println!("fetched from {:?}", response.effective_uri());
+/// # Ok(())
+/// # }
+/// ```
+pub trait ResponseExt {
+    /// Get the URI that was actually fetched to produce this response.
+    ///
+    /// If any redirects were followed, this will be the URI of the final request in the redirect chain rather than the
+    /// URI that was originally requested. Returns `None` if the URI is not known, such as for responses that were not
+    /// produced by a client.
+    fn effective_uri(&self) -> Option<&Uri>;
+
+    /// Get the number of redirects that were followed to produce this response.
+    fn redirect_count(&self) -> u32;
+
+    /// Get the reason phrase the server sent along with the response status code.
+    ///
+    /// Servers are free to send any text as the reason phrase, which may differ from the canonical reason for the
+    /// status code returned by [`StatusCode::canonical_reason`](../http/struct.StatusCode.html#method.canonical_reason).
+    /// Returns `None` if the server did not send a reason phrase, which is always the case for HTTP/2 responses.
+    fn reason_phrase(&self) -> Option<&str>;
+
+    /// Check if the server responded with `304 Not Modified` to a conditional request, meaning that a previously
+    /// fetched copy of the resource is still current.
+    fn is_not_modified(&self) -> bool;
+
+    /// Copy the response body into the given writer, returning the number of bytes written.
+    ///
+    /// The body is streamed through a fixed-size buffer rather than being read into memory all at once, which makes
+    /// this suitable for saving large downloads to a file. I/O errors from reading the body or writing to the writer
+    /// are both returned.
+    ///
+    /// ```rust
+    /// use chttp::ResponseExt;
+    /// use std::fs::File;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let mut response = chttp::get("https://example.org")?;
+    /// let mut file = File::create("example.html")?;
+    /// response.copy_to(&mut file)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<u64>;
+
+    /// Get an iterator over the lines of the response body, which are read as they arrive.
+    ///
+    /// This allows processing a long-lived streaming response, such as a stream of newline-delimited JSON, incrementally
+    /// without waiting for the response to complete. Each line is returned without its trailing newline, and must be
+    /// valid UTF-8.
+    ///
+    /// ```rust
+    /// use chttp::ResponseExt;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let mut response = chttp::get("https://example.org/events")?;
+    /// for line in response.lines() {
+    ///     println!("{}", line?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn lines(&mut self) -> io::Lines<BufReader<&mut Body>>;
+
+    /// Get an iterator over chunks of the response body as they arrive.
+    ///
+    /// Each chunk contains whatever data was available at the time, up to 8 KiB, so chunk boundaries do not
+    /// necessarily line up with any structure of the data.
+    fn chunks(&mut self) -> Chunks<'_>;
+
+    /// Read the response body as a string, decoding it using the character encoding named by the `charset` parameter
+    /// of the `Content-Type` header.
+    ///
+    /// UTF-8 is assumed if no charset is given or the charset is not recognized. Unlike
+    /// [`Body::text`](../body/struct.Body.html#method.text), which requires valid UTF-8, malformed sequences are
+    /// replaced with the Unicode replacement character instead of causing an error, the same as in web browsers.
+    ///
+    /// ```rust
+    /// use chttp::ResponseExt;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let mut response = chttp::get("https://example.org")?;
+    /// println!("{}", response.text()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "text-decoding")]
+    fn text(&mut self) -> Result<String, Error>;
+
+    /// Turn a response with a `4xx` or `5xx` status code into an error, passing through any other response.
+    ///
+    /// The error is an [`Error::Status`](../error/enum.Error.html#variant.Status) that includes the headers of the
+    /// response and up to 64 KiB of its body, which is read before returning. Since reading the body blocks, this
+    /// should not be used inside of an async context. To treat such responses as errors for all requests instead, which
+    /// also works with the async API, use the [`fail_on_error`](../options/struct.Options.html#structfield.fail_on_error)
+    /// option.
+    ///
+    /// ```rust
+    /// use chttp::ResponseExt;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let mut response = chttp::get("https://example.org")?.error_for_status()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn error_for_status(self) -> Result<Response, Error>
+    where
+        Self: Sized;
+
+    /// Replace the response body with a new body created from the current one, keeping the status, headers and
+    /// extensions of the response.
+    ///
+    /// This is handy for wrapping the body stream, such as for counting the bytes read. To take ownership of the body
+    /// without keeping the rest of the response, use [`into_body`](../http/response/struct.Response.html#method.into_body)
+    /// instead.
+    ///
+    /// ```rust
+    /// use chttp::{Body, ResponseExt};
+    /// use std::io::Read;
+    ///
+    /// # fn run() -> Result<(), chttp::Error> {
+    /// let response = chttp::get("https://example.org")?
+    ///     .map_body(|body| Body::from_reader(body.take(1024)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn map_body<F>(self, f: F) -> Response
+    where
+        F: FnOnce(Body) -> Body,
+        Self: Sized;
+}
+
+impl ResponseExt for Response {
+    fn effective_uri(&self) -> Option<&Uri> {
+        self.extensions().get::<EffectiveUri>().map(|uri| &uri.0)
+    }
+
+    fn redirect_count(&self) -> u32 {
+        self.extensions().get::<RedirectCount>().map(|count| count.0).unwrap_or(0)
+    }
+
+    fn reason_phrase(&self) -> Option<&str> {
+        self.extensions().get::<ReasonPhrase>().map(|reason| reason.0.as_str())
+    }
+
+    fn is_not_modified(&self) -> bool {
+        self.status() == http::StatusCode::NOT_MODIFIED
+    }
+
+    fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<u64> {
+        io::copy(self.body_mut(), writer)
+    }
+
+    fn lines(&mut self) -> io::Lines<BufReader<&mut Body>> {
+        BufReader::new(self.body_mut()).lines()
+    }
+
+    fn chunks(&mut self) -> Chunks<'_> {
+        Chunks {
+            body: self.body_mut(),
+            buffer: vec![0; CHUNK_SIZE],
+        }
+    }
+
+    #[cfg(feature = "text-decoding")]
+    fn text(&mut self) -> Result<String, Error> {
+        let encoding = self.headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(charset)
+            .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+
+        let bytes = self.body_mut().bytes()?;
+        let (text, _, _) = encoding.decode(&bytes);
+
+        Ok(text.into_owned())
+    }
+
+    fn error_for_status(self) -> Result<Response, Error> {
+        let status = self.status();
+
+        if status.is_client_error() || status.is_server_error() {
+            Err(Error::from_response(self))
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn map_body<F>(self, f: F) -> Response
+    where
+        F: FnOnce(Body) -> Body,
+    {
+        self.map(f)
+    }
+}
+
+/// An iterator over chunks of a response body.
+///
+/// Returned by [`ResponseExt::chunks`](trait.ResponseExt.html#tymethod.chunks).
+#[derive(Debug)]
+pub struct Chunks<'a> {
+    body: &'a mut Body,
+    buffer: Vec<u8>,
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.body.read(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(amount) => return Some(Ok(self.buffer[..amount].to_vec())),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Get the value of the `charset` parameter of a media type.
+#[cfg(feature = "text-decoding")]
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';')
+        .skip(1)
+        .filter_map(|parameter| {
+            let mut parts = parameter.splitn(2, '=');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+
+            if name.eq_ignore_ascii_case("charset") {
+                Some(value.trim_matches('"'))
+            } else {
+                None
+            }
+        })
+        .next()
+}
@@ -0,0 +1,291 @@
+//! Parsing of [server-sent events](https://html.spec.whatwg.org/multipage/server-sent-events.html) from a
+//! `text/event-stream` response.
+//!
+//! An [`EventStream`](struct.EventStream.html) wraps a response body and yields each event as soon as it has been
+//! received, without waiting for the response to complete:
+//!
+//! ```rust
+//! use chttp::sse::EventStream;
+//!
+//! # fn run() -> Result<(), chttp::Error> {
+//! let response = chttp::get("https://example.org/events")?;
+//!
+//! for event in EventStream::new(response.into_body()) {
+//!     let event = event?;
+//!     println!("{}: {}", event.event, event.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Body;
+use std::io::{self, BufRead, BufReader, Read};
+use std::time::Duration;
+
+/// A single event received from an event stream.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// The ID of the last event received, including this one.
+    ///
+    /// Servers typically only send an ID with some events, so this is the value of the most recent `id` field, which
+    /// should be sent back in a `Last-Event-ID` header when reconnecting.
+    pub id: Option<String>,
+
+    /// The type of the event, which is `message` unless the server gives a different one.
+    pub event: String,
+
+    /// The data of the event. If the server sent multiple `data` fields, they are joined with newlines.
+    pub data: String,
+
+    /// The reconnection time requested by the server in this event, if any.
+    pub retry: Option<Duration>,
+}
+
+/// An iterator over the events of an event stream.
+///
+/// Lines are read from the underlying reader as they arrive, so events are returned as soon as the blank line ending
+/// them has been received. Comment lines are skipped, and an incomplete event at the end of the stream is discarded.
+#[derive(Debug)]
+pub struct EventStream<R = Body> {
+    reader: BufReader<R>,
+    /// Set if the last line ended with a carriage return, in which case a following line feed belongs to it.
+    skip_line_feed: bool,
+    /// Set once the first line has been read, after which a byte order mark is no longer expected.
+    started: bool,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl<R: Read> EventStream<R> {
+    /// Create an event stream reading from the given reader, which is usually a response body.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            skip_line_feed: false,
+            started: false,
+            last_event_id: None,
+            retry: None,
+        }
+    }
+
+    /// Get the ID of the last event received, if any.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_ref().map(String::as_str)
+    }
+
+    /// Get the most recent reconnection time requested by the server, if any.
+    ///
+    /// Unlike [`Event::retry`](struct.Event.html#structfield.retry), this also includes reconnection times sent without
+    /// any event data.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Read the next line, without its line ending. Returns `None` at the end of the stream, discarding an incomplete
+    /// line.
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+
+        loop {
+            let (consumed, done) = {
+                let buf = match self.reader.fill_buf() {
+                    Ok(buf) => buf,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+
+                // A line feed right after a carriage return is part of the same line ending.
+                if self.skip_line_feed {
+                    self.skip_line_feed = false;
+
+                    if buf[0] == b'\n' {
+                        (1, false)
+                    } else {
+                        (0, false)
+                    }
+                } else {
+                    match buf.iter().position(|&byte| byte == b'\n' || byte == b'\r') {
+                        Some(end) => {
+                            line.extend_from_slice(&buf[..end]);
+                            self.skip_line_feed = buf[end] == b'\r';
+                            (end + 1, true)
+                        },
+                        None => {
+                            line.extend_from_slice(buf);
+                            (buf.len(), false)
+                        },
+                    }
+                }
+            };
+
+            self.reader.consume(consumed);
+
+            if done {
+                break;
+            }
+        }
+
+        let mut line = String::from_utf8_lossy(&line).into_owned();
+
+        // The stream may start with a byte order mark, which is not part of the first line.
+        if !self.started {
+            self.started = true;
+
+            if line.starts_with('\u{feff}') {
+                line.remove(0);
+            }
+        }
+
+        Ok(Some(line))
+    }
+}
+
+impl<R: Read> Iterator for EventStream<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut event = None;
+        let mut data = String::new();
+        let mut retry = None;
+
+        loop {
+            let line = match self.read_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            // A blank line dispatches the event, unless it has no data.
+            if line.is_empty() {
+                if data.is_empty() {
+                    event = None;
+                    retry = None;
+                    continue;
+                }
+
+                // Remove the newline added after the last data field.
+                data.pop();
+
+                return Some(Ok(Event {
+                    id: self.last_event_id.clone(),
+                    event: event.unwrap_or_else(|| String::from("message")),
+                    data,
+                    retry,
+                }));
+            }
+
+            // Lines starting with a colon are comments.
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = match line.find(':') {
+                Some(index) => {
+                    let value = &line[index + 1..];
+
+                    // A single space after the colon is not part of the value.
+                    if value.starts_with(' ') {
+                        (&line[..index], &value[1..])
+                    } else {
+                        (&line[..index], value)
+                    }
+                },
+                None => (line.as_str(), ""),
+            };
+
+            match field {
+                "event" => event = Some(value.to_owned()),
+                "data" => {
+                    data.push_str(value);
+                    data.push('\n');
+                },
+                "id" if !value.contains('\0') => {
+                    self.last_event_id = if value.is_empty() {
+                        None
+                    } else {
+                        Some(value.to_owned())
+                    };
+                },
+                "retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                    if let Ok(millis) = value.parse() {
+                        retry = Some(Duration::from_millis(millis));
+                        self.retry = retry;
+                    }
+                },
+                // Unknown fields are ignored.
+                _ => {},
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn events(stream: &str) -> Vec<Event> {
+        EventStream::new(Cursor::new(stream.to_owned()))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    fn event(id: Option<&str>, event: &str, data: &str, retry: Option<u64>) -> Event {
+        Event {
+            id: id.map(ToOwned::to_owned),
+            event: event.to_owned(),
+            data: data.to_owned(),
+            retry: retry.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn parse_simple_events() {
+        assert_eq!(events("data: hello\n\ndata: world\n\n"), vec![
+            event(None, "message", "hello", None),
+            event(None, "message", "world", None),
+        ]);
+    }
+
+    #[test]
+    fn parse_multi_line_data() {
+        assert_eq!(events("data: first\ndata: second\ndata\n\n"), vec![
+            event(None, "message", "first\nsecond\n", None),
+        ]);
+    }
+
+    #[test]
+    fn parse_all_fields() {
+        assert_eq!(events(": comment\nid: 1\nevent: update\nretry: 3000\ndata:no space\n\ndata: next\n\n"), vec![
+            event(Some("1"), "update", "no space", Some(3000)),
+            event(Some("1"), "message", "next", None),
+        ]);
+    }
+
+    #[test]
+    fn parse_line_endings() {
+        assert_eq!(events("\u{feff}data: a\r\n\r\ndata: b\r\rdata: c\n\n"), vec![
+            event(None, "message", "a", None),
+            event(None, "message", "b", None),
+            event(None, "message", "c", None),
+        ]);
+    }
+
+    #[test]
+    fn events_without_data_are_skipped() {
+        assert_eq!(events("event: empty\n\nretry: abc\nid\ndata: x\n\n"), vec![
+            event(None, "message", "x", None),
+        ]);
+    }
+
+    #[test]
+    fn incomplete_event_is_discarded() {
+        assert_eq!(events("data: complete\n\ndata: incomplete\n"), vec![
+            event(None, "message", "complete", None),
+        ]);
+    }
+}
@@ -0,0 +1,642 @@
+mod common;
+
+#[test]
+fn send_all_returns_results_in_order() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.url())
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let requests = (0..5).map(|i| {
+        chttp::http::Request::get(format!("{}/{}", server.endpoint(), i)).body(()).unwrap()
+    });
+
+    let results = client.send_all(requests);
+    assert_eq!(results.len(), 5);
+
+    for (i, result) in results.into_iter().enumerate() {
+        let mut response = result.unwrap();
+        assert_eq!(response.body_mut().text().unwrap(), format!("/{}", i));
+    }
+}
+
+#[test]
+fn metrics_count_requests() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    assert_eq!(client.metrics().requests_sent(), 0);
+
+    for _ in 0..3 {
+        let mut response = client.get(server.endpoint()).unwrap();
+        response.body_mut().text().unwrap();
+    }
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.requests_sent(), 3);
+    assert!(metrics.connections_opened() >= 1);
+}
+
+#[test]
+fn clones_share_connection_pool() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let clone = client.with_options(chttp::Options::default().with_metrics(true));
+
+    client.get(server.endpoint()).unwrap().body_mut().text().unwrap();
+    clone.get(server.endpoint()).unwrap().body_mut().text().unwrap();
+
+    assert_eq!(client.metrics().requests_sent(), 2);
+    assert_eq!(clone.metrics().requests_sent(), 2);
+}
+
+#[test]
+fn default_headers_do_not_override_request_headers() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(format!(
+            "{} {}",
+            request.header("X-Api-Key").unwrap_or(""),
+            request.header("X-Trace").unwrap_or(""),
+        ))
+    });
+
+    let mut headers = chttp::http::HeaderMap::new();
+    headers.insert("X-Api-Key", "default".parse().unwrap());
+    headers.insert("X-Trace", "default".parse().unwrap());
+
+    let client = chttp::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .header("X-Trace", "custom")
+        .body(())
+        .unwrap();
+    let mut response = client.send(request).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "default custom");
+}
+
+#[test]
+fn content_length_of_resource() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+    });
+
+    let client = chttp::Client::new().unwrap();
+
+    assert_eq!(client.content_length(server.endpoint()).unwrap(), Some(11));
+}
+
+#[test]
+fn get_range_returns_partial_content() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.header("Range") {
+            Some("bytes=6-") => rouille::Response::text("world")
+                .with_status_code(206)
+                .with_additional_header("Content-Range", "bytes 6-10/11"),
+            _ => rouille::Response::text("hello world"),
+        }
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let mut response = client.get_range(server.endpoint(), 6, None).unwrap();
+
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body_mut().text().unwrap(), "world");
+}
+
+#[test]
+fn get_range_fails_if_server_ignores_range() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+    });
+
+    let client = chttp::Client::new().unwrap();
+
+    match client.get_range(server.endpoint(), 6, None) {
+        Err(chttp::Error::RangeRequestUnsupported) => {},
+        result => panic!("expected range request unsupported error, got {:?}", result),
+    }
+}
+
+#[test]
+fn download_resume_appends_to_existing_file() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.header("Range") {
+            Some("bytes=5-") => rouille::Response::text(" world")
+                .with_status_code(206)
+                .with_additional_header("Content-Range", "bytes 5-10/11"),
+            _ => rouille::Response::text("hello world"),
+        }
+    });
+
+    let path = std::env::temp_dir().join(format!("chttp-download-resume-{}", std::process::id()));
+    std::fs::write(&path, "hello").unwrap();
+
+    let client = chttp::Client::new().unwrap();
+    let size = client.download_resume(server.endpoint(), &path).unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(size, 11);
+    assert_eq!(contents, "hello world");
+}
+
+#[test]
+fn download_resume_restarts_if_resource_changed() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match (request.header("Range"), request.header("If-Range")) {
+            // The server has a new version, so it ignores the range of the old one.
+            (Some(_), Some("\"v1\"")) | (None, _) => rouille::Response::text("brand new")
+                .with_unique_header("ETag", "\"v2\""),
+            _ => rouille::Response::text("unexpected request").with_status_code(400),
+        }
+    });
+
+    let path = std::env::temp_dir().join(format!("chttp-download-resume-changed-{}", std::process::id()));
+    let validator_path = path.with_extension("resume");
+    std::fs::write(&path, "old").unwrap();
+    std::fs::write(&validator_path, "\"v1\"").unwrap();
+
+    let client = chttp::Client::new().unwrap();
+    let size = client.download_resume(server.endpoint(), &path).unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(size, 9);
+    assert_eq!(contents, "brand new");
+    assert!(!validator_path.exists());
+}
+
+#[test]
+fn download_resume_keeps_complete_file() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.header("Range") {
+            Some("bytes=11-") => rouille::Response::text("")
+                .with_status_code(416)
+                .with_additional_header("Content-Range", "bytes */11"),
+            _ => rouille::Response::text("unexpected request").with_status_code(400),
+        }
+    });
+
+    let path = std::env::temp_dir().join(format!("chttp-download-resume-complete-{}", std::process::id()));
+    let validator_path = path.with_extension("resume");
+    std::fs::write(&path, "hello world").unwrap();
+    std::fs::write(&validator_path, "\"v1\"").unwrap();
+
+    let client = chttp::Client::new().unwrap();
+    let size = client.download_resume(server.endpoint(), &path).unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(size, 11);
+    assert_eq!(contents, "hello world");
+    assert!(!validator_path.exists());
+}
+
+#[cfg(feature = "async-api")]
+#[test]
+fn request_can_be_canceled_with_handle() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        rouille::Response::text("too late")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let future = client.send_async(chttp::http::Request::get(server.endpoint()).body(()).unwrap());
+    let handle = future.handle();
+
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        handle.cancel();
+    });
+
+    match futures::executor::block_on(future) {
+        Err(chttp::Error::Canceled) => {},
+        result => panic!("expected canceled error, got {:?}", result),
+    }
+}
+
+#[test]
+fn exists_maps_status_codes() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.url().as_str() {
+            "/found" => rouille::Response::text(""),
+            "/gone" => rouille::Response::text("").with_status_code(410),
+            "/missing" => rouille::Response::empty_404(),
+            _ => rouille::Response::text("").with_status_code(500),
+        }
+    });
+
+    let client = chttp::Client::new().unwrap();
+
+    assert!(client.exists(format!("{}/found", server.endpoint())).unwrap());
+    assert!(!client.exists(format!("{}/gone", server.endpoint())).unwrap());
+    assert!(!client.exists(format!("{}/missing", server.endpoint())).unwrap());
+
+    let error = client.exists(format!("{}/broken", server.endpoint())).unwrap_err();
+    assert_eq!(error.status(), Some(chttp::http::StatusCode::INTERNAL_SERVER_ERROR));
+}
+
+#[test]
+fn credentials_are_read_from_netrc_file() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.header("Authorization").unwrap_or("none"))
+    });
+
+    let uri: chttp::http::Uri = server.endpoint().parse().unwrap();
+    let path = std::env::temp_dir().join(format!("chttp-netrc-{}", std::process::id()));
+    std::fs::write(&path, format!("machine {} login user password secret\n", uri.host().unwrap())).unwrap();
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default()
+            .with_netrc(chttp::NetrcPolicy::Optional)
+            .with_netrc_file(Some(path.clone())))
+        .body(())
+        .unwrap();
+    let mut response = chttp::send(request).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "Basic dXNlcjpzZWNyZXQ=");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn shutdown_rejects_new_requests() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let clone = client.clone();
+
+    client.get(server.endpoint()).unwrap().body_mut().text().unwrap();
+    client.shutdown(std::time::Duration::from_secs(1));
+
+    match clone.get(server.endpoint()) {
+        Err(chttp::Error::Canceled) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn shutdown_waits_for_requests_in_progress() {
+    use std::time::Duration;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        std::thread::sleep(Duration::from_millis(300));
+        rouille::Response::text("finished")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let clone = client.clone();
+    let endpoint = server.endpoint();
+
+    let request = std::thread::spawn(move || {
+        clone.get(endpoint).and_then(|mut response| response.body_mut().text())
+    });
+
+    // Give the request time to start before shutting down.
+    std::thread::sleep(Duration::from_millis(100));
+    client.shutdown(Duration::from_secs(5));
+
+    assert_eq!(request.join().unwrap().unwrap(), "finished");
+}
+
+#[test]
+fn shutdown_aborts_requests_at_deadline() {
+    use std::time::{Duration, Instant};
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        std::thread::sleep(Duration::from_secs(3));
+        rouille::Response::text("too late")
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let clone = client.clone();
+    let endpoint = server.endpoint();
+
+    let request = std::thread::spawn(move || clone.get(endpoint).map(|response| response.status()));
+
+    // Give the request time to start before shutting down.
+    std::thread::sleep(Duration::from_millis(100));
+    let start = Instant::now();
+    client.shutdown(Duration::from_millis(200));
+
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    match request.join().unwrap() {
+        Err(chttp::Error::Timeout) => {},
+        other => panic!("expected timeout error, got {:?}", other),
+    }
+}
+
+#[test]
+fn configured_user_agent_is_sent_by_default() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.header("User-Agent").unwrap_or(""))
+    });
+
+    let client = chttp::Client::builder()
+        .user_agent("my-app/1.0")
+        .build()
+        .unwrap();
+
+    let mut response = client.get(server.endpoint()).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "my-app/1.0");
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .header("User-Agent", "override/2.0")
+        .body(())
+        .unwrap();
+    let mut response = client.send(request).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "override/2.0");
+}
+
+#[test]
+fn invalid_user_agent_fails_to_build() {
+    common::setup();
+
+    let result = chttp::Client::builder()
+        .user_agent("my-app/1.0\r\nX-Injected: true")
+        .build();
+
+    match result {
+        Err(chttp::Error::InvalidHttpFormat(_)) => {},
+        Err(e) => panic!("unexpected error: {:?}", e),
+        Ok(_) => panic!("client built with an invalid user agent"),
+    }
+}
+
+#[cfg(feature = "async-api")]
+#[test]
+fn request_body_can_be_streamed() {
+    use futures::prelude::*;
+    use std::io::Read;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        let mut body = String::new();
+        request.data().unwrap().read_to_string(&mut body).unwrap();
+        rouille::Response::text(body)
+    });
+
+    // Send the second chunk only after a delay, so that the upload has to wait for it.
+    let (mut sender, receiver) = futures::channel::mpsc::channel::<Result<bytes::Bytes, std::io::Error>>(1);
+    std::thread::spawn(move || {
+        futures::executor::block_on(sender.send(Ok("hello ".into()))).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        futures::executor::block_on(sender.send(Ok("world".into()))).unwrap();
+    });
+
+    let mut response = chttp::post(server.endpoint(), chttp::Body::from_stream(receiver)).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "hello world");
+}
+
+#[test]
+fn extension_methods_are_sent_with_body() {
+    use std::io::Read;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        let mut body = String::new();
+        request.data().unwrap().read_to_string(&mut body).unwrap();
+        rouille::Response::text(format!("{} {}", request.method(), body))
+    });
+
+    for method in &["PATCH", "PURGE", "PROPFIND"] {
+        let request = chttp::http::Request::builder()
+            .method(*method)
+            .uri(server.endpoint())
+            .body("payload")
+            .unwrap();
+        let mut response = chttp::send(request).unwrap();
+
+        assert_eq!(response.body_mut().text().unwrap(), format!("{} payload", method));
+    }
+}
+
+#[test]
+fn socket_callback_is_called_for_new_connections() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    let client = chttp::Client::builder()
+        .options(chttp::Options::default().with_socket_callback(Some(chttp::options::SocketHandler::new(|_| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }))))
+        .build()
+        .unwrap();
+
+    assert_eq!(client.get(server.endpoint()).unwrap().status(), 200);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn socket_callback_error_aborts_request() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    let client = chttp::Client::builder()
+        .options(chttp::Options::default().with_socket_callback(Some(chttp::options::SocketHandler::new(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "nope"))
+        }))))
+        .build()
+        .unwrap();
+
+    assert!(client.get(server.endpoint()).is_err());
+}
+
+#[test]
+fn to_builder_keeps_configuration() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.header("X-Test").unwrap_or("none"))
+    });
+
+    let mut headers = chttp::http::HeaderMap::new();
+    headers.insert("X-Test", "hello".parse().unwrap());
+
+    let client = chttp::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    let sibling = client.to_builder().build().unwrap();
+
+    let mut response = sibling.get(server.endpoint()).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "hello");
+}
+
+#[test]
+fn cookies_option_is_merged_with_cookie_header() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.header("Cookie").unwrap_or("none"))
+    });
+
+    let mut response = chttp::http::Request::get(server.endpoint())
+        .header("Cookie", "foo=1")
+        .extension(chttp::Options::default().with_cookies(Some("bar=2".into())))
+        .body(())
+        .map_err(Into::into)
+        .and_then(chttp::send)
+        .unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "foo=1; bar=2");
+}
+
+#[test]
+fn path_as_is_sends_dot_segments() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.raw_url())
+    });
+
+    let mut response = chttp::http::Request::get(format!("{}/a/../b", server.endpoint()))
+        .extension(chttp::Options::default().with_path_as_is(true))
+        .body(())
+        .map_err(Into::into)
+        .and_then(chttp::send)
+        .unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "/a/../b");
+}
+
+#[test]
+fn conditional_request_options_send_headers() {
+    use chttp::ResponseExt;
+    use std::time::{Duration, SystemTime};
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        assert_eq!(request.header("If-Modified-Since"), Some("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(request.header("If-None-Match"), Some("\"abc\""));
+        rouille::Response::empty_204().with_status_code(304)
+    });
+
+    let response = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default()
+            .with_if_modified_since(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777)))
+            .with_if_none_match(Some("\"abc\"".into())))
+        .body(())
+        .map_err(Into::into)
+        .and_then(chttp::send)
+        .unwrap();
+
+    assert!(response.is_not_modified());
+}
+
+#[test]
+fn fail_on_error_returns_status_error() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("not here").with_status_code(404)
+    });
+
+    let client = chttp::Client::builder()
+        .options(chttp::Options::default().with_fail_on_error(true))
+        .build()
+        .unwrap();
+
+    match client.get(server.endpoint()) {
+        Err(chttp::Error::Status { code, body, .. }) => {
+            assert_eq!(code, 404);
+            assert_eq!(body, b"not here");
+        },
+        result => panic!("expected status error, got {:?}", result),
+    }
+}
+
+#[test]
+fn clients_can_share_an_event_loop() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    let first = chttp::Client::new().unwrap();
+    let second = chttp::Client::builder()
+        .event_loop(first.event_loop().unwrap())
+        .build()
+        .unwrap();
+
+    assert_eq!(first.get(server.endpoint()).unwrap().status(), 200);
+    assert_eq!(second.get(server.endpoint()).unwrap().status(), 200);
+    assert_eq!(first.metrics().requests_sent(), 2);
+}
+
+#[test]
+fn local_port_range_is_used_for_connections() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    // Find a port that is currently free to start the range at.
+    let start = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let count = 10;
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default().with_local_port_range(start, count))
+        .body(())
+        .unwrap();
+
+    let response = chttp::send(request).unwrap();
+    let port = response.extensions().get::<chttp::ConnectionInfo>().unwrap().local_addr().port();
+
+    assert!(port >= start && port < start.saturating_add(count), "port {} not in range", port);
+}
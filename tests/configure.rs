@@ -0,0 +1,22 @@
+mod common;
+
+// The global client can only be configured once per process, so this is the only test in this file.
+#[test]
+fn configure_sets_global_options_until_first_use() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.raw_url())
+    });
+
+    chttp::configure(chttp::Options::default().with_path_as_is(true)).unwrap();
+
+    let mut response = chttp::get(format!("{}/a/../b", server.endpoint())).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), "/a/../b");
+
+    match chttp::configure(chttp::Options::default()) {
+        Err(chttp::Error::AlreadyInitialized) => {},
+        result => panic!("expected already initialized error, got {:?}", result),
+    }
+}
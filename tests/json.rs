@@ -0,0 +1,67 @@
+#![cfg(feature = "json")]
+
+use std::collections::HashMap;
+
+mod common;
+
+#[test]
+fn get_json_deserializes_response_body() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        assert_eq!(request.header("Accept"), Some("application/json"));
+        rouille::Response::text(r#"{"hello": "world"}"#)
+            .with_unique_header("Content-Type", "application/json")
+    });
+
+    let value: HashMap<String, String> = chttp::get_json(server.endpoint()).unwrap();
+
+    assert_eq!(value["hello"], "world");
+}
+
+#[test]
+fn get_json_returns_status_error_without_parsing_body() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("not found").with_status_code(404)
+    });
+
+    match chttp::get_json::<HashMap<String, String>, _>(server.endpoint()) {
+        Err(chttp::Error::Status { code, body, .. }) => {
+            assert_eq!(code, 404);
+            assert_eq!(body, b"not found");
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn post_json_sends_serialized_body() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        assert_eq!(request.header("Content-Type"), Some("application/json"));
+        let mut body = String::new();
+        std::io::Read::read_to_string(&mut request.data().unwrap(), &mut body).unwrap();
+        rouille::Response::text(body)
+    });
+
+    let mut value = HashMap::new();
+    value.insert("hello", "world");
+
+    let mut response = chttp::post_json(server.endpoint(), &value).unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), r#"{"hello":"world"}"#);
+}
+
+#[test]
+fn post_json_fails_before_sending_unserializable_values() {
+    let mut value = HashMap::new();
+    value.insert(vec![1], "keys must be strings");
+
+    match chttp::post_json("http://localhost:1", &value) {
+        Err(chttp::Error::SerializationFailed(_)) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
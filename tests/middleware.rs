@@ -0,0 +1,353 @@
+#![cfg(feature = "middleware-api")]
+
+use chttp::middleware::RetryMiddleware;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+mod common;
+
+#[test]
+fn retry_middleware_retries_unavailable_responses() {
+    common::setup();
+
+    static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|_| {
+        if ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+            rouille::Response::text("try again").with_status_code(503)
+        } else {
+            rouille::Response::text("ok")
+        }
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(RetryMiddleware::new(3).base_delay(Duration::from_millis(10)))
+        .build()
+        .unwrap();
+
+    let mut response = client.get(server.endpoint()).unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body_mut().text().unwrap(), "ok");
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn retry_middleware_does_not_retry_timed_out_posts() {
+    common::setup();
+
+    static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|_| {
+        ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(500));
+        rouille::Response::text("too late")
+    });
+
+    let client = chttp::Client::builder()
+        .options(chttp::Options::default().with_timeout(Some(Duration::from_millis(100))))
+        .with_middleware(RetryMiddleware::new(3).base_delay(Duration::from_millis(10)))
+        .build()
+        .unwrap();
+
+    match client.post(server.endpoint(), "payment") {
+        Err(chttp::Error::Timeout) => {},
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn rate_limit_middleware_spaces_out_requests() {
+    use chttp::middleware::RateLimitMiddleware;
+    use std::time::Instant;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("ok")
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(RateLimitMiddleware::new(10.0).burst(1))
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        client.get(server.endpoint()).unwrap();
+    }
+
+    // The first request uses the burst, and each following one waits for a new token.
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[test]
+fn rate_limit_middleware_accepts_invalid_rates() {
+    use chttp::middleware::RateLimitMiddleware;
+
+    for &rate in &[0.0, -1.0, std::f64::NAN] {
+        let _ = RateLimitMiddleware::new(rate);
+    }
+}
+
+#[test]
+fn cache_middleware_serves_fresh_responses_from_cache() {
+    common::setup();
+
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|_| {
+        REQUESTS.fetch_add(1, Ordering::SeqCst);
+        rouille::Response::text("cached")
+            .with_unique_header("Cache-Control", "max-age=60")
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(chttp::middleware::CacheMiddleware::new())
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let mut response = client.get(server.endpoint()).unwrap();
+        assert_eq!(response.body_mut().text().unwrap(), "cached");
+    }
+
+    assert_eq!(REQUESTS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn cache_middleware_revalidates_stale_responses() {
+    common::setup();
+
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+    static REVALIDATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|request| {
+        REQUESTS.fetch_add(1, Ordering::SeqCst);
+
+        if request.header("If-None-Match") == Some("\"v1\"") {
+            REVALIDATIONS.fetch_add(1, Ordering::SeqCst);
+            rouille::Response::empty_204().with_status_code(304)
+        } else {
+            rouille::Response::text("original")
+                .with_unique_header("Cache-Control", "no-cache")
+                .with_unique_header("ETag", "\"v1\"")
+        }
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(chttp::middleware::CacheMiddleware::new())
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let mut response = client.get(server.endpoint()).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body_mut().text().unwrap(), "original");
+    }
+
+    assert_eq!(REQUESTS.load(Ordering::SeqCst), 2);
+    assert_eq!(REVALIDATIONS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn cache_middleware_merges_headers_from_not_modified_responses() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        if request.header("If-None-Match").is_some() {
+            rouille::Response::empty_204()
+                .with_status_code(304)
+                .with_unique_header("X-Version", "2")
+        } else {
+            rouille::Response::text("original")
+                .with_unique_header("Cache-Control", "no-cache")
+                .with_unique_header("ETag", "\"v1\"")
+                .with_unique_header("X-Version", "1")
+        }
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(chttp::middleware::CacheMiddleware::new())
+        .build()
+        .unwrap();
+
+    let mut response = client.get(server.endpoint()).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "original");
+
+    let mut response = client.get(server.endpoint()).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["X-Version"], "2");
+    assert_eq!(response.headers()["ETag"], "\"v1\"");
+    assert_eq!(response.body_mut().text().unwrap(), "original");
+}
+
+#[test]
+fn cache_middleware_returns_not_modified_for_conditional_requests_from_caller() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        if request.header("If-None-Match") == Some("\"v1\"") {
+            rouille::Response::empty_204().with_status_code(304)
+        } else {
+            rouille::Response::text("original")
+                .with_unique_header("Cache-Control", "no-cache")
+                .with_unique_header("ETag", "\"v1\"")
+        }
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(chttp::middleware::CacheMiddleware::new())
+        .build()
+        .unwrap();
+
+    let mut response = client.get(server.endpoint()).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "original");
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .header("If-None-Match", "\"v1\"")
+        .body(())
+        .unwrap();
+    let response = client.send(request).unwrap();
+    assert_eq!(response.status(), 304);
+
+    // The stored response is still used for requests that are not conditional.
+    let mut response = client.get(server.endpoint()).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body_mut().text().unwrap(), "original");
+}
+
+#[test]
+fn cache_middleware_stores_each_variant_separately() {
+    common::setup();
+
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|request| {
+        REQUESTS.fetch_add(1, Ordering::SeqCst);
+        rouille::Response::text(request.header("Accept-Language").unwrap_or("none"))
+            .with_unique_header("Cache-Control", "max-age=60")
+            .with_unique_header("Vary", "Accept-Language")
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(chttp::middleware::CacheMiddleware::new())
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        for &language in &["en", "de"] {
+            let request = chttp::http::Request::get(server.endpoint())
+                .header("Accept-Language", language)
+                .body(())
+                .unwrap();
+
+            let mut response = client.send(request).unwrap();
+            assert_eq!(response.body_mut().text().unwrap(), language);
+        }
+    }
+
+    assert_eq!(REQUESTS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn idempotency_middleware_deduplicates_repeated_requests() {
+    use chttp::middleware::IdempotencyMiddleware;
+
+    common::setup();
+
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|request| {
+        assert!(request.header("Idempotency-Key").is_some());
+        REQUESTS.fetch_add(1, Ordering::SeqCst);
+        rouille::Response::text("charged")
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(IdempotencyMiddleware::new().window(Duration::from_secs(60)))
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let request = chttp::http::Request::post(server.endpoint())
+            .header("Idempotency-Key", "payment-1")
+            .body("amount=10")
+            .unwrap();
+        let mut response = client.send(request).unwrap();
+        assert_eq!(response.body_mut().text().unwrap(), "charged");
+    }
+
+    // Requests without a key get a fresh one each time.
+    client.post(server.endpoint(), "amount=10").unwrap().body_mut().text().unwrap();
+
+    assert_eq!(REQUESTS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn idempotency_middleware_sends_repeats_while_original_body_is_unread() {
+    use chttp::middleware::IdempotencyMiddleware;
+
+    common::setup();
+
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    let server = common::TestServer::spawn(|_| {
+        REQUESTS.fetch_add(1, Ordering::SeqCst);
+        rouille::Response::text("charged")
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(IdempotencyMiddleware::new().window(Duration::from_secs(60)))
+        .build()
+        .unwrap();
+
+    let send = |uri: String| {
+        let request = chttp::http::Request::post(uri)
+            .header("Idempotency-Key", "payment-2")
+            .body("amount=10")
+            .unwrap();
+        client.send(request).unwrap()
+    };
+
+    // Holding on to the first response without reading it must not block the repeat.
+    let mut first = send(server.endpoint());
+    let mut second = send(server.endpoint());
+    assert_eq!(first.body_mut().text().unwrap(), "charged");
+    assert_eq!(second.body_mut().text().unwrap(), "charged");
+
+    // The same key used for a different URI belongs to a different request.
+    send(format!("{}/other", server.endpoint())).body_mut().text().unwrap();
+
+    assert_eq!(REQUESTS.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn options_set_by_request_middleware_are_applied() {
+    common::setup();
+
+    struct FailOnError;
+
+    impl chttp::middleware::Middleware for FailOnError {
+        fn filter_request(&self, mut request: chttp::Request) -> chttp::Request {
+            request.extensions_mut().insert(chttp::Options::default().with_fail_on_error(true));
+            request
+        }
+    }
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("not here").with_status_code(404)
+    });
+
+    let client = chttp::Client::builder()
+        .with_middleware(FailOnError)
+        .build()
+        .unwrap();
+
+    match client.get(server.endpoint()) {
+        Err(chttp::Error::Status { code, .. }) => assert_eq!(code, 404),
+        result => panic!("expected status error, got {:?}", result),
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "mock")]
+
+use chttp::http::Method;
+use chttp::mock::Mock;
+
+#[test]
+fn mock_returns_canned_responses_and_records_requests() {
+    let mock = Mock::new();
+    mock.respond(Method::GET, "http://example.org/", 200, "hello")
+        .respond(Method::POST, "http://example.org/items", 201, "created");
+
+    let client = chttp::Client::builder().mock(mock.clone()).build().unwrap();
+
+    let mut response = client.get("http://example.org/").unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body_mut().text().unwrap(), "hello");
+
+    let mut response = client.post("http://example.org/items", "new item").unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.body_mut().text().unwrap(), "created");
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].method(), Method::POST);
+    assert_eq!(requests[1].body().as_slice(), b"new item");
+}
+
+#[test]
+fn mock_fails_unmatched_requests() {
+    let mock = Mock::new();
+    mock.respond(Method::GET, "http://example.org/", 200, "hello");
+
+    let client = chttp::Client::builder().mock(mock.clone()).build().unwrap();
+
+    match client.delete("http://example.org/") {
+        Err(chttp::Error::NoResponse) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert_eq!(mock.requests().len(), 1);
+}
+
+#[test]
+fn responders_can_use_the_mock() {
+    let mock = Mock::new();
+    let inner = mock.clone();
+    mock.respond_with(Method::GET, "http://example.org/count", move |_| {
+        chttp::http::Response::new(inner.requests().len().to_string().into())
+    });
+
+    let client = chttp::Client::builder().mock(mock.clone()).build().unwrap();
+
+    // The request currently being answered is recorded once the responder returns.
+    assert_eq!(client.get("http://example.org/count").unwrap().body_mut().text().unwrap(), "0");
+    assert_eq!(client.get("http://example.org/count").unwrap().body_mut().text().unwrap(), "1");
+}
+
+#[test]
+fn mocked_client_applies_options_without_event_loop() {
+    let mock = Mock::new();
+    mock.respond(Method::GET, "http://example.org/", 500, "oops");
+
+    let client = chttp::Client::builder().mock(mock.clone()).build().unwrap();
+    assert!(client.event_loop().is_none());
+    assert_eq!(client.get("http://example.org/").unwrap().status(), 500);
+
+    let strict_client = client.with_options(chttp::Options::default().with_fail_on_error(true));
+
+    match strict_client.get("http://example.org/") {
+        Err(chttp::Error::Status { code, .. }) => assert_eq!(code, 500),
+        other => panic!("expected status error, got {:?}", other),
+    }
+}
@@ -0,0 +1,79 @@
+use chttp::Options;
+
+mod common;
+
+#[test]
+fn blacklisted_host_bypasses_proxy() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("direct")
+    });
+    let port = server.endpoint().rsplit(':').next().unwrap().to_owned();
+
+    // Nothing is listening on the proxy address, so the request only succeeds if the proxy is bypassed.
+    let request = chttp::http::Request::get(format!("http://localhost:{}", port))
+        .extension(Options::default()
+            .with_proxy(Some("http://127.0.0.1:1".parse().unwrap()))
+            .with_proxy_blacklist(vec![String::from("localhost")]))
+        .body(())
+        .unwrap();
+
+    let mut response = chttp::send(request).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "direct");
+}
+
+#[test]
+fn proxy_tunnel_uses_connect() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    common::setup();
+
+    static RECEIVED_CONNECT: AtomicBool = AtomicBool::new(false);
+
+    let proxy = common::TestServer::spawn(|request| {
+        if request.method() == "CONNECT" {
+            RECEIVED_CONNECT.store(true, Ordering::SeqCst);
+        }
+        rouille::Response::text("tunnels are closed").with_status_code(403)
+    });
+
+    let request = chttp::http::Request::get("http://example.org")
+        .extension(Options::default()
+            .with_proxy(Some(proxy.endpoint().parse().unwrap()))
+            .with_proxy_tunnel(true))
+        .body(())
+        .unwrap();
+
+    // The proxy refuses to open the tunnel, so the request itself fails.
+    assert!(chttp::send(request).is_err());
+    assert!(RECEIVED_CONNECT.load(Ordering::SeqCst));
+}
+
+#[test]
+fn proxy_bearer_token_is_sent_to_proxy() {
+    use chttp::options::Authentication;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    common::setup();
+
+    static RECEIVED_TOKEN: AtomicBool = AtomicBool::new(false);
+
+    let proxy = common::TestServer::spawn(|request| {
+        if request.method() == "CONNECT" && request.header("Proxy-Authorization") == Some("Bearer secret") {
+            RECEIVED_TOKEN.store(true, Ordering::SeqCst);
+        }
+        rouille::Response::text("tunnels are closed").with_status_code(403)
+    });
+
+    let request = chttp::http::Request::get("http://example.org")
+        .extension(Options::default()
+            .with_proxy(Some(proxy.endpoint().parse().unwrap()))
+            .with_proxy_tunnel(true)
+            .with_proxy_authentication(Some(Authentication::Bearer(String::from("secret")))))
+        .body(())
+        .unwrap();
+
+    assert!(chttp::send(request).is_err());
+    assert!(RECEIVED_TOKEN.load(Ordering::SeqCst));
+}
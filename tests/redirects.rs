@@ -1,5 +1,7 @@
 use chttp::http::Request;
 use chttp::Options;
+use chttp::ResponseExt;
+use std::sync::Arc;
 
 mod common;
 
@@ -40,6 +42,66 @@ fn response_301_auto_follow() {
 
     assert_eq!(response.status(), 200);
     assert_eq!(response.body_mut().text().unwrap(), "ok");
+    assert_eq!(response.effective_uri().unwrap().to_string(), format!("{}/b", server.endpoint()));
+    assert_eq!(response.redirect_count(), 1);
+}
+
+#[test]
+fn auto_referer_sets_referer_on_redirect() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.raw_url() {
+            "/a" => rouille::Response::redirect_302("/b"),
+            _ => rouille::Response::text(request.header("Referer").unwrap_or("none")),
+        }
+    });
+
+    let mut response = Request::get(format!("{}/a", server.endpoint()))
+        .extension(Options::default()
+            .with_redirect_policy(chttp::options::RedirectPolicy::Follow)
+            .with_auto_referer(true))
+        .body(())
+        .map_err(Into::into)
+        .and_then(chttp::send)
+        .unwrap();
+
+    assert_eq!(response.body_mut().text().unwrap(), format!("{}/a", server.endpoint()));
+}
+
+#[test]
+fn post_redirect_policy_controls_method() {
+    use std::io::Read;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.raw_url() {
+            "/a" => rouille::Response::redirect_302("/b"),
+            _ => {
+                let mut body = String::new();
+                request.data().unwrap().read_to_string(&mut body).unwrap();
+                rouille::Response::text(format!("{} {}", request.method(), body))
+            },
+        }
+    });
+
+    let send = |policy| {
+        Request::post(format!("{}/a", server.endpoint()))
+            .extension(Options::default()
+                .with_redirect_policy(chttp::options::RedirectPolicy::Follow)
+                .with_post_redirect(policy))
+            .body("hello")
+            .map_err(Into::into)
+            .and_then(chttp::send)
+            .unwrap()
+            .body_mut()
+            .text()
+            .unwrap()
+    };
+
+    assert_eq!(send(chttp::options::PostRedirectPolicy::default()), "GET ");
+    assert_eq!(send(chttp::options::PostRedirectPolicy::keep_all()), "POST hello");
 }
 
 #[test]
@@ -66,3 +128,30 @@ fn redirect_limit_is_respected() {
         _ => false,
     });
 }
+
+#[test]
+fn custom_redirect_policy_can_reject_redirect() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.raw_url() {
+            "/a" => rouille::Response::redirect_301("/b"),
+            "/b" => rouille::Response::redirect_301("/c"),
+            _ => rouille::Response::text("ok"),
+        }
+    });
+
+    let response = Request::get(format!("{}/a", server.endpoint()))
+        .extension(Options::default()
+            .with_redirect_policy(chttp::options::RedirectPolicy::Custom(Arc::new(|_, target| {
+                target.path() != "/c"
+            }))))
+        .body(())
+        .map_err(Into::into)
+        .and_then(chttp::send)
+        .unwrap();
+
+    assert_eq!(response.status(), 301);
+    assert_eq!(response.headers()["Location"], "/c");
+    assert_eq!(response.redirect_count(), 1);
+}
@@ -1,5 +1,31 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
 mod common;
 
+/// Spawn a server that answers a single request with the given raw response, for responses that the regular test server
+/// cannot produce. Returns the endpoint of the server.
+fn spawn_raw_server(response: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+
+        // Skip over the request headers, which end with an empty line.
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    format!("http://{}", addr)
+}
+
 #[test]
 fn simple_response_body() {
     common::setup();
@@ -13,6 +39,60 @@ fn simple_response_body() {
     assert_eq!(response_text, "hello world");
 }
 
+#[test]
+fn repeated_response_headers_are_kept() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+            .with_additional_header("Set-Cookie", "foo=1")
+            .with_additional_header("Set-Cookie", "bar=2")
+    });
+
+    let response = chttp::get(server.endpoint()).unwrap();
+    let cookies = response.headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(cookies, vec!["foo=1", "bar=2"]);
+}
+
+#[test]
+fn headers_of_intermediate_responses_are_discarded() {
+    use chttp::options::Authentication;
+
+    common::setup();
+
+    // Answer the first step of an NTLM handshake with a challenge, and accept whatever the client answers with.
+    let server = common::TestServer::spawn(|request| {
+        match request.header("Authorization") {
+            Some(auth) if auth.starts_with("NTLM TlRMTVNTUAAD") => rouille::Response::text("welcome"),
+            Some(auth) if auth.starts_with("NTLM TlRMTVNTUAAB") => rouille::Response::text("challenge")
+                .with_status_code(401)
+                .with_unique_header("WWW-Authenticate", "NTLM TlRMTVNTUAACAAAAAAAAADAAAAABAgAAAQIDBAUGBwgAAAAAAAAAAAAAAAAwAAAA")
+                .with_unique_header("X-Challenge", "yes"),
+            _ => rouille::Response::text("who are you").with_status_code(403),
+        }
+    });
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default().with_authentication(Some(Authentication::Ntlm {
+            username: String::from("user"),
+            password: String::from("secret"),
+        })))
+        .body(())
+        .unwrap();
+
+    let mut response = chttp::send(request).unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key("X-Challenge"));
+    assert!(!response.headers().contains_key("WWW-Authenticate"));
+    assert_eq!(response.body_mut().text().unwrap(), "welcome");
+}
+
 #[test]
 fn large_response_body() {
     common::setup();
@@ -25,3 +105,232 @@ fn large_response_body() {
     let response_text = response.body_mut().text().unwrap();
     assert_eq!(response_text, "wow so large ".repeat(1000));
 }
+
+#[test]
+fn gzip_response_body_is_decoded() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::content_encoding::apply(request, rouille::Response::text("hello world ".repeat(100)))
+    });
+
+    let mut response = chttp::get(server.endpoint()).unwrap();
+
+    assert!(!response.headers().contains_key("Content-Encoding"));
+    assert_eq!(response.body_mut().text().unwrap(), "hello world ".repeat(100));
+}
+
+#[test]
+fn accept_encoding_is_sent_as_configured() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        rouille::Response::text(request.header("Accept-Encoding").unwrap_or("none"))
+    });
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default().with_accept_encoding(Some("gzip".into())))
+        .body(())
+        .unwrap();
+    let mut response = chttp::send(request).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "gzip");
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default().with_accept_encoding(None))
+        .body(())
+        .unwrap();
+    let mut response = chttp::send(request).unwrap();
+    assert_eq!(response.body_mut().text().unwrap(), "none");
+}
+
+#[test]
+fn copy_response_body_to_writer() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+    });
+
+    let mut response = chttp::get(server.endpoint()).unwrap();
+    let mut buffer = Vec::new();
+
+    assert_eq!(response.copy_to(&mut buffer).unwrap(), 11);
+    assert_eq!(buffer, b"hello world");
+}
+
+#[test]
+fn response_larger_than_max_response_size_fails() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("too large ".repeat(100))
+    });
+
+    let request = chttp::http::Request::get(server.endpoint())
+        .extension(chttp::Options::default().with_max_response_size(Some(10)))
+        .body(())
+        .unwrap();
+
+    // The response has a known length, so it is rejected before its body is received.
+    match chttp::send(request) {
+        Err(chttp::Error::ResponseTooLarge) => {},
+        result => panic!("expected response too large error, got {:?}", result),
+    }
+}
+
+#[cfg(feature = "async-api")]
+#[test]
+fn read_response_body_asynchronously() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world ".repeat(1000))
+    });
+
+    let client = chttp::Client::new().unwrap();
+    let request = chttp::http::Request::get(server.endpoint()).body(()).unwrap();
+
+    let text = futures::executor::block_on(async {
+        let mut response = client.send_async(request).await?;
+        response.body_mut().text_async().await
+    }).unwrap();
+
+    assert_eq!(text, "hello world ".repeat(1000));
+}
+
+#[test]
+fn custom_reason_phrase_is_preserved() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let endpoint = spawn_raw_server("HTTP/1.1 499 Client Went Away\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+
+    let response = chttp::get(endpoint).unwrap();
+
+    assert_eq!(response.status(), 499);
+    assert_eq!(response.reason_phrase(), Some("Client Went Away"));
+}
+
+#[test]
+fn standard_reason_phrase_is_exposed() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    let response = chttp::get(server.endpoint()).unwrap();
+
+    assert_eq!(response.reason_phrase(), Some("OK"));
+}
+
+#[test]
+fn map_body_keeps_status_and_headers() {
+    use chttp::{Body, ResponseExt};
+    use std::io::Read;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("hello world")
+            .with_status_code(202)
+            .with_unique_header("X-Test", "yes")
+    });
+
+    let mut response = chttp::get(server.endpoint()).unwrap()
+        .map_body(|body| Body::from_reader(body.take(5)));
+
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.headers()["X-Test"], "yes");
+    assert_eq!(response.body_mut().text().unwrap(), "hello");
+}
+
+#[test]
+fn response_version_matches_server() {
+    common::setup();
+
+    // HTTP/1.1 is the default version of a response, so use an older one to see that it is really read.
+    let endpoint = spawn_raw_server("HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+
+    let mut response = chttp::get(endpoint).unwrap();
+
+    assert_eq!(response.version(), chttp::http::Version::HTTP_10);
+    assert_eq!(response.body_mut().text().unwrap(), "hello");
+}
+
+#[cfg(feature = "text-decoding")]
+#[test]
+fn text_is_decoded_using_charset() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::from_data("text/plain; charset=ISO-8859-1", vec![b'c', b'a', b'f', 0xe9])
+    });
+
+    let mut response = chttp::get(server.endpoint()).unwrap();
+
+    assert_eq!(response.text().unwrap(), "caf\u{e9}");
+}
+
+#[test]
+fn lines_and_chunks_iterate_over_body() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| {
+        rouille::Response::text("{\"a\":1}\n{\"b\":2}\n")
+    });
+
+    let mut response = chttp::get(server.endpoint()).unwrap();
+    let lines = response.lines().collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(lines, vec!["{\"a\":1}", "{\"b\":2}"]);
+
+    let mut response = chttp::get(server.endpoint()).unwrap();
+    let body = response.chunks().collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+    assert_eq!(body, b"{\"a\":1}\n{\"b\":2}\n");
+}
+
+#[test]
+fn connection_info_is_attached_to_response() {
+    common::setup();
+
+    let server = common::TestServer::spawn(|_| rouille::Response::text("ok"));
+
+    let response = chttp::get(server.endpoint()).unwrap();
+    let info = response.extensions().get::<chttp::ConnectionInfo>().unwrap();
+
+    assert!(server.endpoint().ends_with(&info.remote_addr().to_string()));
+    assert!(info.local_addr().ip().is_loopback());
+}
+
+#[test]
+fn error_for_status_fails_on_client_and_server_errors() {
+    use chttp::ResponseExt;
+
+    common::setup();
+
+    let server = common::TestServer::spawn(|request| {
+        match request.raw_url() {
+            "/ok" => rouille::Response::text("ok"),
+            _ => rouille::Response::text("oops").with_status_code(500),
+        }
+    });
+
+    assert!(chttp::get(format!("{}/ok", server.endpoint())).unwrap().error_for_status().is_ok());
+
+    match chttp::get(format!("{}/fail", server.endpoint())).unwrap().error_for_status() {
+        Err(chttp::Error::Status { code, body, .. }) => {
+            assert_eq!(code, 500);
+            assert_eq!(body, b"oops");
+        },
+        result => panic!("expected status error, got {:?}", result),
+    }
+}